@@ -0,0 +1,448 @@
+use url::Url;
+
+use crate::hash::{self, random_u64};
+use crate::header::{is_tchar, Header};
+
+/// A single challenge from a `WWW-Authenticate` header.
+///
+/// <https://www.rfc-editor.org/rfc/rfc7235#section-2.1>
+///
+/// A challenge consists of an auth scheme (such as `Basic` or `Digest`) followed
+/// by either a list of parameters (`realm="example"`) or a single `token68` value.
+///
+/// ```
+/// # fn main() -> Result<(), ureq::Error> {
+/// let s = "HTTP/1.1 401 Unauthorized\r\n\
+///     WWW-Authenticate: Digest realm=\"x\", nonce=\"y\", Basic realm=\"z\"\r\n\
+///     \r\n";
+/// let resp: ureq::Response = s.parse()?;
+///
+/// let challenges = resp.auth_challenges();
+/// assert_eq!(challenges.len(), 2);
+/// assert_eq!(challenges[0].scheme(), "Digest");
+/// assert_eq!(challenges[0].param("nonce"), Some("y"));
+/// assert_eq!(challenges[1].scheme(), "Basic");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthChallenge {
+    scheme: String,
+    params: Vec<(String, String)>,
+    token68: Option<String>,
+}
+
+impl AuthChallenge {
+    fn new(scheme: &str) -> Self {
+        AuthChallenge {
+            scheme: scheme.to_string(),
+            params: vec![],
+            token68: None,
+        }
+    }
+
+    /// The auth scheme, as sent by the server, i.e. `Digest`.
+    pub fn scheme(&self) -> &str {
+        &self.scheme
+    }
+
+    /// Compares the given str to the auth scheme ignoring case.
+    pub fn is_scheme(&self, scheme: &str) -> bool {
+        self.scheme.eq_ignore_ascii_case(scheme)
+    }
+
+    /// The value of the named parameter (case insensitive), with any quoting removed.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// All parameters of this challenge, in the order they were sent.
+    pub fn params(&self) -> Vec<(&str, &str)> {
+        self.params
+            .iter()
+            .map(|(n, v)| (n.as_str(), v.as_str()))
+            .collect()
+    }
+
+    /// The `token68` value for schemes that use one instead of parameters,
+    /// i.e. `Negotiate` in `WWW-Authenticate: Negotiate abc123==`.
+    pub fn token68(&self) -> Option<&str> {
+        self.token68.as_deref()
+    }
+}
+
+/// Parse the value of one `WWW-Authenticate` header into challenges.
+///
+/// Unparseable trailing input is ignored, and whatever challenges were
+/// successfully read up until that point are returned.
+pub(crate) fn parse_challenges(value: &str) -> Vec<AuthChallenge> {
+    let mut parser = Parser {
+        input: value.as_bytes(),
+        pos: 0,
+    };
+    let mut challenges = vec![];
+
+    loop {
+        parser.skip_list_separators();
+        let scheme = parser.token();
+        if scheme.is_empty() {
+            break;
+        }
+        let mut challenge = AuthChallenge::new(scheme);
+
+        // The params (or token68) follow the scheme after at least one space.
+        let mut seen_comma = false;
+        loop {
+            let before = parser.pos;
+            seen_comma |= parser.skip_list_separators();
+            let name = parser.token68();
+            if name.is_empty() {
+                break;
+            }
+            parser.skip_whitespace();
+
+            let equals = parser.count(b'=');
+            if equals == 0 {
+                if challenge.params.is_empty() && challenge.token68.is_none() && !seen_comma {
+                    challenge.token68 = Some(name.to_string());
+                    continue;
+                }
+                // This is the scheme of the next challenge.
+                parser.pos = before;
+                break;
+            }
+
+            parser.skip_whitespace();
+            let is_token68 = equals > 1 || parser.at_end() || parser.peek() == Some(b',');
+            if is_token68 && challenge.params.is_empty() && !seen_comma {
+                challenge.token68 = Some(format!("{}{}", name, "=".repeat(equals)));
+                continue;
+            }
+
+            let value = if parser.peek() == Some(b'"') {
+                parser.quoted_string()
+            } else {
+                parser.token().to_string()
+            };
+            challenge.params.push((name.to_string(), value));
+        }
+
+        challenges.push(challenge);
+    }
+
+    challenges
+}
+
+/// Credentials for HTTP Digest authentication.
+///
+/// <https://www.rfc-editor.org/rfc/rfc7616>
+///
+/// *Internal API*
+#[derive(Clone)]
+pub(crate) struct DigestAuth {
+    username: String,
+    password: String,
+}
+
+impl std::fmt::Debug for DigestAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DigestAuth")
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DigestAuth {
+    pub(crate) fn new(username: &str, password: &str) -> Self {
+        DigestAuth {
+            username: username.to_string(),
+            password: password.to_string(),
+        }
+    }
+
+    /// Create an `Authorization` header answering the first Digest challenge
+    /// we support, or `None` if there is no such challenge.
+    pub(crate) fn authorization(
+        &self,
+        method: &str,
+        url: &Url,
+        challenges: &[AuthChallenge],
+    ) -> Option<Header> {
+        let challenge = challenges.iter().find(|c| {
+            c.is_scheme("digest")
+                && c.param("nonce").is_some()
+                && DigestAlgorithm::from_param(c.param("algorithm")).is_some()
+                && qop_of(c) != Some(None)
+        })?;
+
+        let uri = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+
+        let value = self.response(method, &uri, challenge, &random_cnonce());
+        Some(Header::new("Authorization", &value))
+    }
+
+    fn response(&self, method: &str, uri: &str, challenge: &AuthChallenge, cnonce: &str) -> String {
+        // Checked in authorization()
+        let algorithm = DigestAlgorithm::from_param(challenge.param("algorithm")).unwrap();
+        let nonce = challenge.param("nonce").unwrap_or_default();
+        let realm = challenge.param("realm").unwrap_or_default();
+        let qop = qop_of(challenge).flatten();
+        // We only ever answer a nonce once, so the nonce count is always 1.
+        let nc = "00000001";
+
+        let h = |s: String| algorithm.hash(s.as_bytes());
+
+        let mut ha1 = h(format!("{}:{}:{}", self.username, realm, self.password));
+        if algorithm.is_session() {
+            ha1 = h(format!("{}:{}:{}", ha1, nonce, cnonce));
+        }
+        let ha2 = h(format!("{}:{}", method, uri));
+        let response = match qop {
+            Some(qop) => h(format!(
+                "{}:{}:{}:{}:{}:{}",
+                ha1, nonce, nc, cnonce, qop, ha2
+            )),
+            None => h(format!("{}:{}:{}", ha1, nonce, ha2)),
+        };
+
+        let mut value = format!(
+            "Digest username=\"{}\", realm=\"{}\", nonce=\"{}\", uri=\"{}\", response=\"{}\"",
+            quote(&self.username),
+            quote(realm),
+            quote(nonce),
+            quote(uri),
+            response
+        );
+        if let Some(param) = challenge.param("algorithm") {
+            value.push_str(&format!(", algorithm={}", param));
+        }
+        if let Some(qop) = qop {
+            value.push_str(&format!(", qop={}, nc={}, cnonce=\"{}\"", qop, nc, cnonce));
+        }
+        if let Some(opaque) = challenge.param("opaque") {
+            value.push_str(&format!(", opaque=\"{}\"", quote(opaque)));
+        }
+        value
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DigestAlgorithm {
+    Md5,
+    Md5Sess,
+    Sha256,
+    Sha256Sess,
+}
+
+impl DigestAlgorithm {
+    fn from_param(param: Option<&str>) -> Option<Self> {
+        // No algorithm means MD5 for backwards compatibility with RFC 2069.
+        let param = param.unwrap_or("MD5");
+        let algorithm = if param.eq_ignore_ascii_case("MD5") {
+            DigestAlgorithm::Md5
+        } else if param.eq_ignore_ascii_case("MD5-sess") {
+            DigestAlgorithm::Md5Sess
+        } else if param.eq_ignore_ascii_case("SHA-256") {
+            DigestAlgorithm::Sha256
+        } else if param.eq_ignore_ascii_case("SHA-256-sess") {
+            DigestAlgorithm::Sha256Sess
+        } else {
+            return None;
+        };
+        Some(algorithm)
+    }
+
+    fn is_session(&self) -> bool {
+        matches!(self, DigestAlgorithm::Md5Sess | DigestAlgorithm::Sha256Sess)
+    }
+
+    fn hash(&self, data: &[u8]) -> String {
+        match self {
+            DigestAlgorithm::Md5 | DigestAlgorithm::Md5Sess => hash::hex(&hash::md5(data)),
+            DigestAlgorithm::Sha256 | DigestAlgorithm::Sha256Sess => hash::hex(&hash::sha256(data)),
+        }
+    }
+}
+
+// The qop to answer with. `None` if the server didn't send a qop (legacy RFC 2069),
+// `Some(None)` if it only offers qops we don't support (i.e. auth-int).
+fn qop_of(challenge: &AuthChallenge) -> Option<Option<&'static str>> {
+    let qop = challenge.param("qop")?;
+    let has_auth = qop
+        .split(',')
+        .any(|q| q.trim().eq_ignore_ascii_case("auth"));
+    Some(if has_auth { Some("auth") } else { None })
+}
+
+fn quote(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// The client nonce only protects against chosen-plaintext attacks by a server,
+// which unique values are enough for.
+fn random_cnonce() -> String {
+    format!("{:016x}{:016x}", random_u64(), random_u64())
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.input.len()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ') | Some(b'\t')) {
+            self.pos += 1;
+        }
+    }
+
+    // Returns true if at least one comma was skipped.
+    fn skip_list_separators(&mut self) -> bool {
+        let mut comma = false;
+        while let Some(b) = self.peek() {
+            match b {
+                b' ' | b'\t' => {}
+                b',' => comma = true,
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        comma
+    }
+
+    fn count(&mut self, c: u8) -> usize {
+        let start = self.pos;
+        while self.peek() == Some(c) {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    fn take_while(&mut self, f: impl Fn(&u8) -> bool) -> &'a str {
+        let start = self.pos;
+        while self.peek().map(|b| f(&b)).unwrap_or(false) {
+            self.pos += 1;
+        }
+        // All accepted bytes are ascii, so this is valid utf-8.
+        std::str::from_utf8(&self.input[start..self.pos]).unwrap_or_default()
+    }
+
+    fn token(&mut self) -> &'a str {
+        self.take_while(is_tchar)
+    }
+
+    // token68 = 1*( ALPHA / DIGIT / "-" / "." / "_" / "~" / "+" / "/" ) *"="
+    // The trailing "=" are handled by the caller, since they are ambiguous with
+    // the "=" of an auth-param.
+    fn token68(&mut self) -> &'a str {
+        self.take_while(|b| is_tchar(b) || *b == b'/')
+    }
+
+    fn quoted_string(&mut self) -> String {
+        // opening quote
+        self.pos += 1;
+        let mut value = vec![];
+        while let Some(b) = self.peek() {
+            self.pos += 1;
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    if let Some(escaped) = self.peek() {
+                        value.push(escaped);
+                        self.pos += 1;
+                    }
+                }
+                _ => value.push(b),
+            }
+        }
+        String::from_utf8_lossy(&value).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Response;
+
+    #[test]
+    fn digest_and_basic_in_one_header() {
+        let c = parse_challenges(r#"Digest realm="x", nonce="y", Basic realm="z""#);
+        assert_eq!(c.len(), 2);
+        assert_eq!(c[0].scheme(), "Digest");
+        assert_eq!(c[0].params(), vec![("realm", "x"), ("nonce", "y")]);
+        assert_eq!(c[1].scheme(), "Basic");
+        assert_eq!(c[1].params(), vec![("realm", "z")]);
+    }
+
+    #[test]
+    fn quoted_commas_and_escapes() {
+        let c = parse_challenges(r#"Digest realm="a, \"b\"",qop="auth,auth-int" , algorithm=MD5"#);
+        assert_eq!(c.len(), 1);
+        assert_eq!(c[0].param("realm"), Some(r#"a, "b""#));
+        assert_eq!(c[0].param("QOP"), Some("auth,auth-int"));
+        assert_eq!(c[0].param("algorithm"), Some("MD5"));
+    }
+
+    #[test]
+    fn token68_and_bare_schemes() {
+        let c = parse_challenges("Negotiate abc+/12==, NTLM, Bearer");
+        assert_eq!(c.len(), 3);
+        assert_eq!(c[0].token68(), Some("abc+/12=="));
+        assert!(c[1].is_scheme("ntlm"));
+        assert!(c[1].params().is_empty());
+        assert_eq!(c[2].scheme(), "Bearer");
+    }
+
+    #[test]
+    fn digest_rfc2617_example() {
+        let auth = DigestAuth::new("Mufasa", "Circle Of Life");
+        let c = parse_challenges(
+            r#"Digest realm="testrealm@host.com", qop="auth,auth-int", nonce="dcd98b7102dd2f0e8b11d0f600bfb0c093", opaque="5ccc069c403ebaf9f0171e9517f40e41""#,
+        );
+        let value = auth.response("GET", "/dir/index.html", &c[0], "0a4f113b");
+        assert_eq!(
+            value,
+            "Digest username=\"Mufasa\", realm=\"testrealm@host.com\", \
+             nonce=\"dcd98b7102dd2f0e8b11d0f600bfb0c093\", uri=\"/dir/index.html\", \
+             response=\"6629fae49393a05397450978507c4ef1\", qop=auth, nc=00000001, \
+             cnonce=\"0a4f113b\", opaque=\"5ccc069c403ebaf9f0171e9517f40e41\""
+        );
+    }
+
+    #[test]
+    fn digest_unsupported_challenges() {
+        let auth = DigestAuth::new("user", "pass");
+        let url: Url = "http://example.com/".parse().unwrap();
+        let c = parse_challenges(
+            r#"Digest nonce="a", algorithm=SHA-512-256, Digest nonce="b", qop="auth-int", Basic realm="x""#,
+        );
+        assert_eq!(auth.authorization("GET", &url, &c), None);
+    }
+
+    #[test]
+    fn multiple_headers() {
+        let s = "HTTP/1.1 401 Unauthorized\r\n\
+                 WWW-Authenticate: Bearer error=\"invalid_token\"\r\n\
+                 www-authenticate: Basic realm=\"z\"\r\n\
+                 \r\n";
+        let resp = s.parse::<Response>().unwrap();
+        let c = resp.auth_challenges();
+        assert_eq!(c.len(), 2);
+        assert_eq!(c[0].param("error"), Some("invalid_token"));
+        assert_eq!(c[1].param("realm"), Some("z"));
+    }
+}
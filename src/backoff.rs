@@ -0,0 +1,97 @@
+//! Delays between attempts to connect, for [`AgentBuilder::connect_retries`](crate::AgentBuilder::connect_retries).
+
+use std::time::Duration;
+
+use crate::hash::random_u64;
+
+/// How long to wait before retrying a failed connection.
+///
+/// Set with [`AgentBuilder::retry_backoff`](crate::AgentBuilder::retry_backoff).
+/// The default is `ExponentialJitter` from 100 milliseconds up to 10 seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BackoffPolicy {
+    /// The same delay before every retry.
+    Fixed(Duration),
+    /// Double the delay on every retry, starting at `initial` and never above `max`.
+    Exponential {
+        /// Delay before the first retry.
+        initial: Duration,
+        /// Upper bound of the delay.
+        max: Duration,
+    },
+    /// Like `Exponential`, but every delay is picked at random between zero and
+    /// the exponential delay. This spreads out the retries of many clients that
+    /// failed at the same time.
+    ExponentialJitter {
+        /// Delay before the first retry, before jitter.
+        initial: Duration,
+        /// Upper bound of the delay.
+        max: Duration,
+    },
+}
+
+impl Default for BackoffPolicy {
+    fn default() -> Self {
+        BackoffPolicy::ExponentialJitter {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(10),
+        }
+    }
+}
+
+impl BackoffPolicy {
+    /// The delay before retry number `retry`, counting from zero.
+    pub fn delay(&self, retry: u32) -> Duration {
+        match *self {
+            BackoffPolicy::Fixed(delay) => delay,
+            BackoffPolicy::Exponential { initial, max } => exponential(initial, max, retry),
+            BackoffPolicy::ExponentialJitter { initial, max } => {
+                exponential(initial, max, retry).mul_f64(random_fraction())
+            }
+        }
+    }
+}
+
+fn exponential(initial: Duration, max: Duration, retry: u32) -> Duration {
+    1_u32
+        .checked_shl(retry)
+        .and_then(|factor| initial.checked_mul(factor))
+        .map_or(max, |delay| delay.min(max))
+}
+
+// A number in [0, 1).
+fn random_fraction() -> f64 {
+    (random_u64() >> 11) as f64 / (1_u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exponential_delays() {
+        let policy = BackoffPolicy::Exponential {
+            initial: Duration::from_millis(100),
+            max: Duration::from_millis(300),
+        };
+        let delays: Vec<_> = (0..4).map(|retry| policy.delay(retry)).collect();
+        let millis: Vec<_> = delays.iter().map(|d| d.as_millis()).collect();
+        assert_eq!(millis, vec![100, 200, 300, 300]);
+        assert_eq!(policy.delay(u32::MAX), Duration::from_millis(300));
+    }
+
+    #[test]
+    fn jitter_below_exponential() {
+        let policy = BackoffPolicy::ExponentialJitter {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+        };
+        for retry in 0..5 {
+            assert!(
+                policy.delay(retry)
+                    <= Duration::from_millis(100 << retry).min(Duration::from_secs(1))
+            );
+        }
+    }
+}
@@ -1,11 +1,19 @@
-use crate::stream::Stream;
+use std::cell::RefCell;
 use std::fmt;
-use std::io::{self, copy, empty, Cursor, Read, Write};
+use std::io::{self, copy, empty, Cursor, Read, Seek, SeekFrom, Write};
+use std::sync::Mutex;
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+
+use crate::checksum::{Checksum, ChecksumReader};
+use crate::hash;
 
 #[cfg(feature = "charset")]
 use crate::response::DEFAULT_CHARACTER_SET;
 #[cfg(feature = "charset")]
 use encoding_rs::Encoding;
+#[cfg(feature = "gzip")]
+use flate2::write::GzEncoder;
 
 /// The different kinds of bodies to send.
 ///
@@ -15,6 +23,12 @@ pub(crate) enum Payload<'a> {
     Text(&'a str, String),
     Reader(Box<dyn Read + 'a>),
     Bytes(&'a [u8]),
+    /// A reader that can be created again to resend the body.
+    Factory(&'a dyn Fn() -> Box<dyn Read>),
+    /// A reader of the given length that is seeked back to the start to resend the body.
+    Seekable(&'a RefCell<dyn ReadSeek + 'a>, u64),
+    /// A reader whose checksum is sent as a trailer.
+    Checksummed(Box<dyn Read + 'a>, Checksum),
 }
 
 impl fmt::Debug for Payload<'_> {
@@ -24,6 +38,9 @@ impl fmt::Debug for Payload<'_> {
             Payload::Text(t, _) => write!(f, "{}", t),
             Payload::Reader(_) => write!(f, "Reader"),
             Payload::Bytes(v) => write!(f, "{:?}", v),
+            Payload::Factory(_) => write!(f, "Factory"),
+            Payload::Seekable(_, len) => write!(f, "Seekable({})", len),
+            Payload::Checksummed(_, checksum) => write!(f, "Checksummed({:?})", checksum),
         }
     }
 }
@@ -51,6 +68,12 @@ pub(crate) enum BodySize {
 pub(crate) struct SizedReader<'a> {
     pub size: BodySize,
     pub reader: Box<dyn Read + 'a>,
+    /// Creates the reader anew, if the body can be resent.
+    pub factory: Option<&'a dyn Fn() -> Box<dyn Read>>,
+    /// Reader and length to read the body again from the start, if the body can be resent.
+    pub seekable: Option<(&'a RefCell<dyn ReadSeek + 'a>, u64)>,
+    /// Checksum to send as a trailer, if the body is chunked.
+    pub checksum: Option<Checksum>,
 }
 
 impl fmt::Debug for SizedReader<'_> {
@@ -61,11 +84,68 @@ impl fmt::Debug for SizedReader<'_> {
 
 impl<'a> SizedReader<'a> {
     fn new(size: BodySize, reader: Box<dyn Read + 'a>) -> Self {
-        SizedReader { size, reader }
+        SizedReader {
+            size,
+            reader,
+            factory: None,
+            seekable: None,
+            checksum: None,
+        }
+    }
+
+    /// Compress the body with gzip, in memory. Not for bodies that can be resent
+    /// from a factory or seekable reader, which would be compressed only once.
+    #[cfg(feature = "gzip")]
+    pub(crate) fn gzip(mut self) -> io::Result<Self> {
+        debug_assert!(self.factory.is_none() && self.seekable.is_none());
+        let mut encoder = GzEncoder::new(vec![], flate2::Compression::default());
+        copy(&mut self.reader, &mut encoder)?;
+        let compressed = encoder.finish()?;
+        let size = BodySize::Known(compressed.len() as u64);
+        Ok(SizedReader {
+            checksum: self.checksum,
+            ..SizedReader::new(size, Box::new(Cursor::new(compressed)))
+        })
+    }
+}
+
+impl SizedReader<'_> {
+    /// Read the body into memory and compute its base64 MD5 digest, for the
+    /// `Content-MD5` header. Fails for bodies larger than `max_size`.
+    pub(crate) fn content_md5(&mut self, max_size: u64) -> io::Result<String> {
+        let mut body = vec![];
+        (&mut self.reader)
+            .take(max_size + 1)
+            .read_to_end(&mut body)?;
+        if body.len() as u64 > max_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "body is too large to compute Content-MD5 (over {} bytes)",
+                    max_size
+                ),
+            ));
+        }
+        let digest = BASE64_STANDARD.encode(hash::md5(&body));
+        self.reader = Box::new(Cursor::new(body));
+        Ok(digest)
     }
 }
 
 impl<'a> Payload<'a> {
+    /// The bytes of an in-memory payload, before any charset encoding.
+    pub fn preview(&self) -> Option<&[u8]> {
+        match self {
+            Payload::Text(text, _) => Some(text.as_bytes()),
+            Payload::Bytes(bytes) => Some(bytes),
+            Payload::Empty
+            | Payload::Reader(_)
+            | Payload::Factory(_)
+            | Payload::Seekable(_, _)
+            | Payload::Checksummed(_, _) => None,
+        }
+    }
+
     pub fn into_read(self) -> SizedReader<'a> {
         match self {
             Payload::Empty => SizedReader::new(BodySize::Empty, Box::new(empty())),
@@ -89,7 +169,49 @@ impl<'a> Payload<'a> {
                 let cursor = Cursor::new(bytes);
                 SizedReader::new(BodySize::Known(len as u64), Box::new(cursor))
             }
+            Payload::Factory(factory) => SizedReader {
+                factory: Some(factory),
+                ..SizedReader::new(BodySize::Unknown, factory())
+            },
+            Payload::Seekable(reader, len) => {
+                let rewound = Rewound {
+                    inner: reader,
+                    rewound: false,
+                };
+                SizedReader {
+                    seekable: Some((reader, len)),
+                    ..SizedReader::new(BodySize::Known(len), Box::new(rewound.take(len)))
+                }
+            }
+            Payload::Checksummed(read, checksum) => SizedReader {
+                checksum: Some(checksum),
+                ..SizedReader::new(BodySize::Unknown, read)
+            },
+        }
+    }
+}
+
+/// A reader that can be seeked back to the start.
+///
+/// *Internal API*
+pub(crate) trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// Reads a shared reader from its start, seeking there before the first read.
+struct Rewound<'a> {
+    inner: &'a RefCell<dyn ReadSeek + 'a>,
+    rewound: bool,
+}
+
+impl Read for Rewound<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut inner = self.inner.borrow_mut();
+        if !self.rewound {
+            inner.seek(SeekFrom::Start(0))?;
+            self.rewound = true;
         }
+        inner.read(buf)
     }
 }
 
@@ -104,17 +226,33 @@ const CHUNK_MAX_PAYLOAD_SIZE: usize = CHUNK_MAX_SIZE - CHUNK_HEADER_MAX_SIZE - C
 // 2) chunked_transfer's Encoder issues 4 separate write() per chunk. This is costly
 //    overhead. Instead, we do a single write() per chunk.
 // The measured benefit on a Linux machine is a 50% reduction in CPU usage on a https connection.
-fn copy_chunked<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> io::Result<u64> {
+fn copy_chunked<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    chunk: &mut Vec<u8>,
+) -> io::Result<u64> {
+    copy_chunked_with_trailer(reader, writer, chunk, |_| String::new())
+}
+
+// Like copy_chunked(), but after the last chunk sends the trailer fields returned
+// by `trailer`, which is called once the reader is exhausted.
+fn copy_chunked_with_trailer<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    chunk: &mut Vec<u8>,
+    trailer: impl FnOnce(&R) -> String,
+) -> io::Result<u64> {
     // The chunk layout is:
     // header:header_max_size | payload:max_payload_size | footer:footer_size
-    let mut chunk = Vec::with_capacity(CHUNK_MAX_SIZE);
+    chunk.clear();
+    chunk.reserve(CHUNK_MAX_SIZE);
     let mut written = 0;
     loop {
         // We first read the payload
         chunk.resize(CHUNK_HEADER_MAX_SIZE, 0);
         let payload_size = reader
             .take(CHUNK_MAX_PAYLOAD_SIZE as u64)
-            .read_to_end(&mut chunk)?;
+            .read_to_end(chunk)?;
 
         // Then write the header
         let header_str = format!("{:x}\r\n", payload_size);
@@ -123,16 +261,82 @@ fn copy_chunked<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> io::Result
         let start_index = CHUNK_HEADER_MAX_SIZE - header.len();
         (&mut chunk[start_index..]).write_all(header).unwrap();
 
+        // On EOF, we write a 0 sized chunk. This is what the chunked encoding protocol
+        // requires. The trailer section comes before its footer.
+        if payload_size == 0 {
+            chunk.extend_from_slice(trailer(reader).as_bytes());
+            chunk.extend_from_slice(b"\r\n");
+            writer.write_all(&chunk[start_index..])?;
+            return Ok(written);
+        }
+
         // And add the footer
         chunk.extend_from_slice(b"\r\n");
 
         // Finally Write the chunk
         writer.write_all(&chunk[start_index..])?;
         written += payload_size as u64;
+    }
+}
 
-        // On EOF, we wrote a 0 sized chunk. This is what the chunked encoding protocol requires.
-        if payload_size == 0 {
-            return Ok(written);
+/// Reader of newline delimited JSON, serializing the items as they are read.
+#[cfg(feature = "json")]
+pub(crate) struct NdjsonReader<I> {
+    items: I,
+    line: Vec<u8>,
+    pos: usize,
+}
+
+#[cfg(feature = "json")]
+impl<I> NdjsonReader<I> {
+    pub fn new(items: I) -> Self {
+        NdjsonReader {
+            items,
+            line: vec![],
+            pos: 0,
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl<T: serde::Serialize, I: Iterator<Item = T>> Read for NdjsonReader<I> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.line.len() {
+            let item = match self.items.next() {
+                Some(item) => item,
+                None => return Ok(0),
+            };
+            self.line.clear();
+            self.pos = 0;
+            serde_json::to_writer(&mut self.line, &item)?;
+            self.line.push(b'\n');
+        }
+        let n = (&self.line[self.pos..]).read(buf)?;
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Buffers for writing chunked bodies, kept by the agent to be reused between requests.
+#[derive(Debug, Default)]
+pub(crate) struct BufferPool(Mutex<Vec<Vec<u8>>>);
+
+impl BufferPool {
+    /// How many idle buffers to keep. More requests can send at once, they
+    /// just allocate their own buffer.
+    const MAX_IDLE: usize = 4;
+
+    fn take(&self) -> Vec<u8> {
+        let mut buffers = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        buffers
+            .pop()
+            .unwrap_or_else(|| Vec::with_capacity(CHUNK_MAX_SIZE))
+    }
+
+    fn give_back(&self, buffer: Vec<u8>) {
+        let mut buffers = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if buffers.len() < Self::MAX_IDLE {
+            buffers.push(buffer);
         }
     }
 }
@@ -141,13 +345,23 @@ fn copy_chunked<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> io::Result
 pub(crate) fn send_body(
     mut body: SizedReader,
     do_chunk: bool,
-    stream: &mut Stream,
+    stream: &mut impl Write,
+    buffers: &BufferPool,
 ) -> io::Result<()> {
-    if do_chunk {
-        copy_chunked(&mut body.reader, stream)?;
-    } else {
+    if !do_chunk {
         copy(&mut body.reader, stream)?;
+        return Ok(());
+    }
+
+    let mut chunk = buffers.take();
+    let result = if let Some(checksum) = body.checksum {
+        let mut reader = ChecksumReader::new(body.reader, checksum);
+        copy_chunked_with_trailer(&mut reader, stream, &mut chunk, |r| r.trailer())
+    } else {
+        copy_chunked(&mut body.reader, stream, &mut chunk)
     };
+    buffers.give_back(chunk);
+    result?;
 
     Ok(())
 }
@@ -163,7 +377,7 @@ mod tests {
         source.extend_from_slice(b"hello world");
 
         let mut dest = Vec::<u8>::new();
-        copy_chunked(&mut &source[..], &mut dest).unwrap();
+        copy_chunked(&mut &source[..], &mut dest, &mut vec![]).unwrap();
 
         let mut dest_expected = Vec::<u8>::new();
         dest_expected.extend_from_slice(format!("{:x}\r\n", CHUNK_MAX_PAYLOAD_SIZE).as_bytes());
@@ -175,4 +389,62 @@ mod tests {
 
         assert_eq!(dest, dest_expected);
     }
+
+    #[test]
+    fn send_body_reuses_buffer() {
+        let buffers = BufferPool::default();
+        let long = vec![b'x'; CHUNK_MAX_PAYLOAD_SIZE + 100];
+        let mut long_expected = format!("{:x}\r\n", CHUNK_MAX_PAYLOAD_SIZE).into_bytes();
+        long_expected.extend_from_slice(&long[..CHUNK_MAX_PAYLOAD_SIZE]);
+        long_expected.extend_from_slice(b"\r\n64\r\n");
+        long_expected.extend_from_slice(&long[CHUNK_MAX_PAYLOAD_SIZE..]);
+        long_expected.extend_from_slice(b"\r\n0\r\n\r\n");
+
+        for _ in 0..10 {
+            let mut dest = vec![];
+            let body = SizedReader::new(BodySize::Unknown, Box::new(&long[..]));
+            send_body(body, true, &mut dest, &buffers).unwrap();
+            assert_eq!(dest, long_expected);
+
+            // A shorter body after a longer one must not pick up stale bytes.
+            let mut dest = vec![];
+            let body = SizedReader::new(BodySize::Unknown, Box::new(&b"hello"[..]));
+            send_body(body, true, &mut dest, &buffers).unwrap();
+            assert_eq!(dest, b"5\r\nhello\r\n0\r\n\r\n");
+        }
+        assert_eq!(buffers.0.lock().unwrap().len(), 1);
+    }
+
+    // A microbench of chunked sends with a reused buffer against a new one per send.
+    // Run with: cargo test --release send_body_buffer_bench -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn send_body_buffer_bench() {
+        use std::time::Instant;
+
+        const SENDS: usize = 100_000;
+        let body = vec![b'x'; 1024];
+        let send = |buffers: &BufferPool| {
+            let reader = SizedReader::new(BodySize::Unknown, Box::new(&body[..]));
+            send_body(reader, true, &mut io::sink(), buffers).unwrap();
+        };
+
+        let pooled = BufferPool::default();
+        let started = Instant::now();
+        for _ in 0..SENDS {
+            send(&pooled);
+        }
+        let pooled_time = started.elapsed();
+
+        let started = Instant::now();
+        for _ in 0..SENDS {
+            send(&BufferPool::default());
+        }
+        let fresh_time = started.elapsed();
+
+        println!(
+            "{} chunked sends: pooled {:?}, fresh {:?}",
+            SENDS, pooled_time, fresh_time
+        );
+    }
 }
@@ -0,0 +1,108 @@
+//! Checksums of request bodies, sent as a trailer after the last chunk.
+
+use std::io::{self, Read};
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+
+use crate::hash::{self, Sha256};
+
+/// Algorithm for [`Request::send_with_body_checksum`](crate::Request::send_with_body_checksum).
+///
+/// The checksum is sent base64 encoded in a trailer named after the algorithm,
+/// for instance `x-checksum-sha256`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Checksum {
+    /// CRC-32 (IEEE), as used by gzip and zlib. Sent in `x-checksum-crc32`.
+    Crc32,
+    /// SHA-256. Sent in `x-checksum-sha256`.
+    Sha256,
+}
+
+impl Checksum {
+    /// The name of the trailer that carries the checksum.
+    pub fn trailer_name(&self) -> &'static str {
+        match self {
+            Checksum::Crc32 => "x-checksum-crc32",
+            Checksum::Sha256 => "x-checksum-sha256",
+        }
+    }
+}
+
+enum Hasher {
+    Crc32(u32),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    fn new(checksum: Checksum) -> Self {
+        match checksum {
+            Checksum::Crc32 => Hasher::Crc32(0),
+            Checksum::Sha256 => Hasher::Sha256(Sha256::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Crc32(crc) => *crc = hash::crc32(*crc, data),
+            Hasher::Sha256(sha) => sha.update(data),
+        }
+    }
+
+    fn finish(&self) -> Vec<u8> {
+        match self {
+            Hasher::Crc32(crc) => crc.to_be_bytes().to_vec(),
+            Hasher::Sha256(sha) => sha.clone().finish().to_vec(),
+        }
+    }
+}
+
+/// Reader that computes the checksum of everything read through it.
+pub(crate) struct ChecksumReader<R> {
+    reader: R,
+    checksum: Checksum,
+    hasher: Hasher,
+}
+
+impl<R: Read> ChecksumReader<R> {
+    pub fn new(reader: R, checksum: Checksum) -> Self {
+        ChecksumReader {
+            reader,
+            checksum,
+            hasher: Hasher::new(checksum),
+        }
+    }
+
+    /// The trailer field with the checksum of the bytes read so far, including the CRLF.
+    pub fn trailer(&self) -> String {
+        let value = BASE64_STANDARD.encode(self.hasher.finish());
+        format!("{}: {}\r\n", self.checksum.trailer_name(), value)
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reader_trailer() {
+        let mut reader = ChecksumReader::new(&b"hello world"[..], Checksum::Crc32);
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        assert_eq!(reader.trailer(), "x-checksum-crc32: DUoRhQ==\r\n");
+
+        let mut reader = ChecksumReader::new(&b"hello world"[..], Checksum::Sha256);
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        assert_eq!(
+            reader.trailer(),
+            "x-checksum-sha256: uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=\r\n"
+        );
+    }
+}
@@ -44,8 +44,14 @@ pub struct Decoder<R> {
     // remaining size of the chunk being read
     // none if we are not in a chunk
     remaining_chunks_size: Option<usize>,
+
+    // names of the trailer fields that must follow the last chunk
+    required_trailers: Vec<String>,
 }
 
+// Limit on the combined size of the trailer fields.
+const MAX_TRAILER_SIZE: usize = 100 * 1_024;
+
 impl<R> Decoder<R>
 where
     R: Read,
@@ -54,9 +60,15 @@ where
         Decoder {
             source,
             remaining_chunks_size: None,
+            required_trailers: vec![],
         }
     }
 
+    /// Fail the read of the last chunk unless these trailer fields were sent.
+    pub fn require_trailers(&mut self, names: Vec<String>) {
+        self.required_trailers = names;
+    }
+
     /// Returns the remaining bytes left in the chunk being read.
     pub fn remaining_chunks_size(&self) -> Option<usize> {
         self.remaining_chunks_size
@@ -112,6 +124,43 @@ where
         Ok(chunk_size)
     }
 
+    // Reads the trailer section after the last chunk, up to and including the
+    // final empty line, and returns the names of the fields in it.
+    fn read_trailers(&mut self) -> IoResult<Vec<String>> {
+        let mut names = vec![];
+        let mut size = 0;
+        loop {
+            let mut line = Vec::new();
+            loop {
+                let mut byte = [0];
+                match self.source.read_exact(&mut byte) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                        return Err(IoError::new(ErrorKind::InvalidInput, DecoderError))
+                    }
+                    Err(e) => return Err(e),
+                }
+                let byte = byte[0];
+                if byte == b'\r' {
+                    break;
+                }
+                size += 1;
+                if size > MAX_TRAILER_SIZE {
+                    return Err(IoError::new(ErrorKind::InvalidInput, DecoderError));
+                }
+                line.push(byte);
+            }
+            self.read_line_feed()?;
+
+            if line.is_empty() {
+                return Ok(names);
+            }
+            if let Some(colon) = line.iter().position(|b| *b == b':') {
+                names.push(String::from_utf8_lossy(&line[..colon]).trim().to_string());
+            }
+        }
+    }
+
     fn read_carriage_return(&mut self) -> IoResult<()> {
         match self.source.by_ref().bytes().next() {
             Some(Ok(b'\r')) => Ok(()),
@@ -141,8 +190,16 @@ where
 
                 // if the chunk size is 0, we are at EOF
                 if chunk_size == 0 {
-                    self.read_carriage_return()?;
-                    self.read_line_feed()?;
+                    let trailers = self.read_trailers()?;
+                    let missing = self
+                        .required_trailers
+                        .iter()
+                        .find(|name| !trailers.iter().any(|t| t.eq_ignore_ascii_case(name)));
+                    if let Some(name) = missing {
+                        let err = crate::ErrorKind::MissingTrailer
+                            .msg(format!("declared trailer {} was not sent", name));
+                        return Err(IoError::new(ErrorKind::InvalidData, err));
+                    }
                     return Ok(0);
                 }
 
@@ -270,6 +327,33 @@ mod test {
         assert_eq!(decoded, "");
     }
 
+    #[test]
+    fn test_decode_trailers() {
+        let mut decoder = Decoder::new(b"3\r\nhey\r\n0\r\nX-Checksum: 1\r\n\r\n" as &[u8]);
+        decoder.require_trailers(vec!["x-checksum".to_string()]);
+
+        let mut decoded = String::new();
+        decoder.read_to_string(&mut decoded).unwrap();
+
+        assert_eq!(decoded, "hey");
+    }
+
+    #[test]
+    fn test_decode_missing_trailer() {
+        let mut decoder = Decoder::new(b"3\r\nhey\r\n0\r\nX-Other: 1\r\n\r\n" as &[u8]);
+        decoder.require_trailers(vec!["X-Checksum".to_string()]);
+
+        let mut decoded = String::new();
+        let err = decoder.read_to_string(&mut decoded).unwrap_err();
+        let err = err
+            .into_inner()
+            .unwrap()
+            .downcast::<crate::Error>()
+            .unwrap();
+
+        assert_eq!(err.kind(), crate::ErrorKind::MissingTrailer);
+    }
+
     #[test]
     fn test_decode_invalid_chunk_length() {
         let mut decoder = Decoder::new(b"m\r\n\r\n" as &[u8]);
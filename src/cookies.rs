@@ -30,7 +30,7 @@ impl CookieTin {
         CookieStoreGuard(lock)
     }
 
-    pub(crate) fn get_request_cookies(&self, url: &Url) -> Vec<cookie::Cookie> {
+    pub(crate) fn get_request_cookies(&self, url: &Url) -> Vec<cookie::Cookie<'static>> {
         let store = self.inner.read().unwrap();
         store
             .get_request_values(url)
@@ -46,3 +46,39 @@ impl CookieTin {
         store.store_response_cookies(cookies, url);
     }
 }
+
+/// Whether the cookie domain is a public suffix, that no single site owns, by
+/// the [Public Suffix List](https://publicsuffix.org/). Every single label domain
+/// is treated as a public suffix as well.
+pub(crate) fn is_public_suffix(domain: &str) -> bool {
+    let domain = domain
+        .trim_start_matches('.')
+        .trim_end_matches('.')
+        .to_ascii_lowercase();
+    if !domain.contains('.') {
+        return true;
+    }
+    match psl::suffix(domain.as_bytes()) {
+        Some(suffix) => suffix.as_bytes() == domain.as_bytes(),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn public_suffixes() {
+        assert!(is_public_suffix("com"));
+        assert!(is_public_suffix(".com"));
+        assert!(is_public_suffix("co.uk"));
+        assert!(is_public_suffix("CO.UK"));
+        assert!(!is_public_suffix("example.com"));
+        assert!(!is_public_suffix("example.co.uk"));
+        // Suffixes run by companies are in the list too.
+        assert!(is_public_suffix("github.io"));
+        assert!(is_public_suffix("blogspot.com"));
+        assert!(!is_public_suffix("example.github.io"));
+    }
+}
@@ -0,0 +1,293 @@
+//! A minimal DNS client over UDP, for [`AgentBuilder::dns_servers`](crate::AgentBuilder::dns_servers).
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use log::debug;
+
+use crate::hash::random_u64;
+use crate::resolve::{Resolver, StdResolver};
+
+/// How long to wait for the answer of one server before asking the next.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const RCODE_NXDOMAIN: u8 = 3;
+
+/// Resolves names by asking the given DNS servers, in order, instead of the system resolver.
+#[derive(Debug)]
+pub(crate) struct DnsResolver {
+    servers: Vec<SocketAddr>,
+    fallback: bool,
+}
+
+impl DnsResolver {
+    pub fn new(servers: Vec<SocketAddr>, fallback: bool) -> Self {
+        DnsResolver { servers, fallback }
+    }
+
+    /// The IPv4 and then IPv6 addresses of `host`, from the first server that answers.
+    fn lookup(&self, host: &str) -> io::Result<Answer> {
+        let mut last_err = None;
+        for server in &self.servers {
+            let ipv4 = match query(*server, host, TYPE_A) {
+                Ok(Answer::Ips(ips)) => ips,
+                Ok(Answer::Truncated) => return Ok(Answer::Truncated),
+                Err(e) => {
+                    debug!("dns server {} failed for {}: {}", server, host, e);
+                    last_err = Some(e);
+                    continue;
+                }
+            };
+            let ips = match query(*server, host, TYPE_AAAA) {
+                Ok(Answer::Ips(ipv6)) => [ipv4, ipv6].concat(),
+                Ok(Answer::Truncated) => return Ok(Answer::Truncated),
+                // The IPv4 addresses are enough to connect to.
+                Err(e) if !ipv4.is_empty() => {
+                    debug!("dns server {} failed for AAAA {}: {}", server, host, e);
+                    ipv4
+                }
+                Err(e) => {
+                    debug!("dns server {} failed for {}: {}", server, host, e);
+                    last_err = Some(e);
+                    continue;
+                }
+            };
+            if ips.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no address for {}", host),
+                ));
+            }
+            return Ok(Answer::Ips(ips));
+        }
+        Err(last_err.expect("at least one dns server"))
+    }
+}
+
+impl Resolver for DnsResolver {
+    fn resolve(&self, netloc: &str) -> io::Result<Vec<SocketAddr>> {
+        let (host, port) = netloc
+            .rsplit_once(':')
+            .and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?)))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "missing port in netloc"))?;
+
+        // Addresses need no lookup.
+        let literal = host.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(ip) = literal.parse::<IpAddr>() {
+            return Ok(vec![SocketAddr::new(ip, port)]);
+        }
+
+        match self.lookup(host) {
+            Ok(Answer::Ips(ips)) => Ok(ips
+                .into_iter()
+                .map(|ip| SocketAddr::new(ip, port))
+                .collect()),
+            // Reading the whole answer needs DNS over TCP, which the system resolver has.
+            Ok(Answer::Truncated) => {
+                debug!("truncated dns answer, using system resolver for {}", host);
+                StdResolver.resolve(netloc)
+            }
+            Err(e) if self.fallback => {
+                debug!("falling back to system resolver for {}: {}", host, e);
+                StdResolver.resolve(netloc)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// What a server answered to a query.
+enum Answer {
+    Ips(Vec<IpAddr>),
+    /// The answer didn't fit in a UDP datagram.
+    Truncated,
+}
+
+fn query(server: SocketAddr, host: &str, qtype: u16) -> io::Result<Answer> {
+    let id = random_u64() as u16;
+    let request = encode_query(id, host, qtype)?;
+
+    let bind: SocketAddr = if server.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(bind)?;
+    socket.connect(server)?;
+    socket.set_read_timeout(Some(QUERY_TIMEOUT))?;
+    socket.send(&request)?;
+
+    let mut buf = [0; 4096];
+    loop {
+        let n = socket.recv(&mut buf)?;
+        // Stray datagrams for other queries are skipped.
+        if let Some(answer) = decode_response(id, &buf[..n])? {
+            return Ok(answer);
+        }
+    }
+}
+
+fn encode_query(id: u16, host: &str, qtype: u16) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(18 + host.len());
+    buf.extend_from_slice(&id.to_be_bytes());
+    // Standard query, recursion desired, one question.
+    buf.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in host.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid dns name {}", host),
+            ));
+        }
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label.as_bytes());
+    }
+    buf.push(0);
+    buf.extend_from_slice(&qtype.to_be_bytes());
+    // Class IN.
+    buf.extend_from_slice(&[0, 1]);
+    Ok(buf)
+}
+
+/// The answer to query `id`, or `None` if it's a response to something else.
+fn decode_response(id: u16, buf: &[u8]) -> io::Result<Option<Answer>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed dns response");
+
+    if buf.len() < 12 || u16_at(buf, 0) != Some(id) || buf[2] & 0x80 == 0 {
+        return Ok(None);
+    }
+    match buf[3] & 0x0f {
+        0 => {}
+        RCODE_NXDOMAIN => return Ok(Some(Answer::Ips(vec![]))),
+        rcode => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("dns server answered with error code {}", rcode),
+            ))
+        }
+    }
+    // The TC bit.
+    if buf[2] & 0x02 != 0 {
+        return Ok(Some(Answer::Truncated));
+    }
+    let questions = u16_at(buf, 4).ok_or_else(invalid)?;
+    let answers = u16_at(buf, 6).ok_or_else(invalid)?;
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = skip_name(buf, pos).ok_or_else(invalid)? + 4;
+    }
+    let mut ips = vec![];
+    for _ in 0..answers {
+        pos = skip_name(buf, pos).ok_or_else(invalid)?;
+        let rtype = u16_at(buf, pos).ok_or_else(invalid)?;
+        let len = u16_at(buf, pos + 8).ok_or_else(invalid)? as usize;
+        let data = buf.get(pos + 10..pos + 10 + len).ok_or_else(invalid)?;
+        match (rtype, len) {
+            (TYPE_A, 4) => ips.push(IpAddr::from([data[0], data[1], data[2], data[3]])),
+            (TYPE_AAAA, 16) => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(data);
+                ips.push(IpAddr::from(octets));
+            }
+            // CNAMEs and the like come before the addresses they lead to.
+            _ => {}
+        }
+        pos += 10 + len;
+    }
+    Ok(Some(Answer::Ips(ips)))
+}
+
+/// The position after the name starting at `pos`.
+fn skip_name(buf: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *buf.get(pos)? as usize;
+        if len == 0 {
+            return Some(pos + 1);
+        }
+        // A pointer to a name elsewhere ends the name.
+        if len & 0xc0 == 0xc0 {
+            return Some(pos + 2);
+        }
+        pos += 1 + len;
+    }
+}
+
+fn u16_at(buf: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*buf.get(pos)?, *buf.get(pos + 1)?]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    // Answers A queries for `mock.test` and `v4only.test` with 10.1.2.3 behind a
+    // CNAME, AAAA queries for `mock.test` with nothing, queries for `localhost`
+    // with a truncated answer, and everything else with a server failure.
+    fn mock_dns_server() -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut buf = [0; 512];
+            while let Ok((n, peer)) = socket.recv_from(&mut buf) {
+                let query = &buf[..n];
+                let end = skip_name(query, 12).unwrap();
+                let name = &query[12..end];
+                let qtype = u16_at(query, end).unwrap();
+
+                let mut resp = query.to_vec();
+                resp[2] |= 0x80;
+                let mock = name == b"\x04mock\x04test\x00";
+                let v4only = name == b"\x06v4only\x04test\x00";
+                if name == b"\x09localhost\x00" {
+                    resp[2] |= 0x02;
+                } else if qtype == TYPE_A && (mock || v4only) {
+                    resp[7] = 2;
+                    // Pointers to the question name at offset 12.
+                    resp.extend_from_slice(
+                        b"\xc0\x0c\x00\x05\x00\x01\x00\x00\x00\x3c\x00\x02\xc0\x0c",
+                    );
+                    resp.extend_from_slice(b"\xc0\x0c\x00\x01\x00\x01\x00\x00\x00\x3c\x00\x04");
+                    resp.extend_from_slice(&[10, 1, 2, 3]);
+                } else if !mock {
+                    resp[3] = 2;
+                }
+                socket.send_to(&resp, peer).unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn resolve_with_server() {
+        let resolver = DnsResolver::new(vec![mock_dns_server()], false);
+        let addrs = resolver.resolve("mock.test:8080").unwrap();
+        assert_eq!(addrs, vec!["10.1.2.3:8080".parse().unwrap()]);
+
+        let err = resolver.resolve("other.test:80").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        // A failed AAAA query keeps the IPv4 addresses.
+        let addrs = resolver.resolve("v4only.test:80").unwrap();
+        assert_eq!(addrs, vec!["10.1.2.3:80".parse().unwrap()]);
+
+        // Truncated answers are left to the system resolver, even without fallback.
+        let addrs = resolver.resolve("localhost:80").unwrap();
+        assert!(addrs.iter().all(|a| a.ip().is_loopback()));
+
+        // Addresses are used as they are.
+        let addrs = resolver.resolve("[::1]:80").unwrap();
+        assert_eq!(addrs, vec!["[::1]:80".parse().unwrap()]);
+    }
+
+    #[test]
+    fn resolve_fallback() {
+        let resolver = DnsResolver::new(vec![mock_dns_server()], true);
+        let addrs = resolver.resolve("localhost:80").unwrap();
+        assert!(addrs.iter().all(|a| a.ip().is_loopback()));
+    }
+}
@@ -269,7 +269,7 @@ impl Error {
         })
     }
 
-    pub(crate) fn url(self, url: Url) -> Self {
+    pub(crate) fn with_url(self, url: Url) -> Self {
         if let Error::Transport(mut e) = self {
             e.url = Some(url);
             Error::Transport(e)
@@ -302,6 +302,24 @@ impl Error {
         }
     }
 
+    /// The URL being requested when the error happened.
+    ///
+    /// After redirects, this is the URL that failed rather than the one the
+    /// request was made to. For [`Error::Status`], it's the URL of the response.
+    ///
+    /// ```
+    /// # ureq::is_test(true);
+    /// let err = ureq::get("http://httpbin.org/status/500")
+    ///     .call().unwrap_err();
+    /// assert_eq!(err.url(), Some("http://httpbin.org/status/500"));
+    /// ```
+    pub fn url(&self) -> Option<&str> {
+        match self {
+            Error::Status(_, response) => Some(response.get_url()),
+            Error::Transport(Transport { url, .. }) => url.as_ref().map(Url::as_str),
+        }
+    }
+
     /// Return true iff the error was due to a connection closing.
     pub(crate) fn connection_closed(&self) -> bool {
         if self.kind() != ErrorKind::Io {
@@ -328,7 +346,15 @@ impl Error {
 }
 
 /// One of the types of error the can occur when processing a Request.
+///
+/// With the `serde` feature, this serializes as the snake case name of the
+/// variant, such as `"too_many_redirects"`.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum ErrorKind {
     /// The url could not be understood.
     InvalidUrl,
@@ -357,7 +383,25 @@ pub enum ErrorKind {
     /// HTTP status code indicating an error (e.g. 4xx, 5xx)
     /// Read the inner response body for details and to return
     /// the connection to the pool.
+    #[cfg_attr(feature = "serde", serde(rename = "http"))]
     HTTP,
+    /// A trailer field declared in the `Trailer` header was not sent.
+    MissingTrailer,
+    /// A response header line was longer than the configured maximum.
+    HeadersTooLarge,
+    /// The agent's limit on open connections was reached, and no connection was
+    /// freed in time.
+    TooManyConnections,
+    /// A compressed response body decompressed to more than the configured
+    /// maximum size or compression ratio.
+    DecompressionBomb,
+    /// A redirect pointed to a url with a scheme other than http or https.
+    UnsupportedRedirectScheme,
+    /// A redirect led back to a url already requested in the same chain of
+    /// redirects, see [`AgentBuilder::detect_redirect_loops`](crate::AgentBuilder::detect_redirect_loops).
+    RedirectLoop,
+    /// The url of a request, including the query, was longer than the configured maximum.
+    UrlTooLong,
 }
 
 impl ErrorKind {
@@ -398,6 +442,97 @@ impl From<ParseError> for Error {
     }
 }
 
+/// Structured form of an error, for logging.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct SerializedError<'a> {
+    kind: ErrorKind,
+    url: Option<&'a str>,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    io: Option<SerializedIoError>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct SerializedIoError {
+    kind: String,
+    message: String,
+}
+
+#[cfg(feature = "serde")]
+impl Transport {
+    fn serialized(&self) -> SerializedError<'_> {
+        let io = self
+            .source
+            .as_ref()
+            .and_then(|s| s.downcast_ref::<io::Error>())
+            .map(|e| SerializedIoError {
+                kind: snake_case(&format!("{:?}", e.kind())),
+                message: e.to_string(),
+            });
+        SerializedError {
+            kind: self.kind,
+            url: self.url.as_ref().map(Url::as_str),
+            message: match &self.message {
+                Some(message) => message.clone(),
+                None => self.kind.to_string(),
+            },
+            status: None,
+            io,
+        }
+    }
+}
+
+// `TimedOut` -> `timed_out`, for the names of io::ErrorKind.
+#[cfg(feature = "serde")]
+fn snake_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len() + 4);
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            out.push('_');
+        }
+        out.push(c.to_ascii_lowercase());
+    }
+    out
+}
+
+/// Serializes as a structure with the kind, url and message of the error.
+///
+/// ```json
+/// { "kind": "too_many_redirects", "url": "https://example.com/", "message": "Too Many Redirects" }
+/// ```
+///
+/// Errors caused by an `io::Error` also have `"io": { "kind": "timed_out", "message": "..." }`,
+/// with the snake case name of its [`io::ErrorKind`], and [`Error::Status`] has
+/// `"status": 404`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Error {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Error::Status(status, response) => SerializedError {
+                kind: ErrorKind::HTTP,
+                url: Some(response.get_url()),
+                message: format!("status code {}", status),
+                status: Some(*status),
+                io: None,
+            }
+            .serialize(serializer),
+            Error::Transport(transport) => transport.serialize(serializer),
+        }
+    }
+}
+
+/// Serializes like [`Error::Transport`].
+#[cfg(feature = "serde")]
+impl serde::Serialize for Transport {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.serialized().serialize(serializer)
+    }
+}
+
 impl fmt::Display for ErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -416,6 +551,13 @@ impl fmt::Display for ErrorKind {
             ErrorKind::ProxyConnect => write!(f, "Proxy failed to connect"),
             ErrorKind::ProxyUnauthorized => write!(f, "Provided proxy credentials are incorrect"),
             ErrorKind::HTTP => write!(f, "HTTP status error"),
+            ErrorKind::MissingTrailer => write!(f, "Missing trailer"),
+            ErrorKind::HeadersTooLarge => write!(f, "Headers Too Large"),
+            ErrorKind::TooManyConnections => write!(f, "Too many connections"),
+            ErrorKind::DecompressionBomb => write!(f, "Decompression bomb"),
+            ErrorKind::UnsupportedRedirectScheme => write!(f, "Unsupported Redirect Scheme"),
+            ErrorKind::RedirectLoop => write!(f, "Redirect Loop"),
+            ErrorKind::UrlTooLong => write!(f, "Url Too Long"),
         }
     }
 }
@@ -469,13 +611,45 @@ mod tests {
         let ioe = io::Error::new(io::ErrorKind::TimedOut, "too slow");
         let mut err = Error::new(ErrorKind::Io, Some("oops".to_string())).src(ioe);
 
-        err = err.url("http://example.com/".parse().unwrap());
+        err = err.with_url("http://example.com/".parse().unwrap());
         assert_eq!(
             err.to_string(),
             "http://example.com/: Network Error: oops: too slow"
         );
     }
 
+    #[test]
+    #[cfg(feature = "json")]
+    fn serialize_too_many_redirects() {
+        let err = ErrorKind::TooManyRedirects
+            .new()
+            .with_url("http://example.com/a".parse().unwrap());
+        assert_eq!(
+            serde_json::to_value(&err).unwrap(),
+            serde_json::json!({
+                "kind": "too_many_redirects",
+                "url": "http://example.com/a",
+                "message": "Too Many Redirects",
+            })
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn serialize_io_error() {
+        let ioe = io::Error::new(io::ErrorKind::TimedOut, "too slow");
+        let err = ErrorKind::Io.msg("oops").src(ioe);
+        assert_eq!(
+            serde_json::to_value(&err).unwrap(),
+            serde_json::json!({
+                "kind": "io",
+                "url": null,
+                "message": "oops",
+                "io": { "kind": "timed_out", "message": "too slow" },
+            })
+        );
+    }
+
     #[test]
     fn connection_closed() {
         let ioe = io::Error::new(io::ErrorKind::ConnectionReset, "connection reset");
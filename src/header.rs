@@ -177,6 +177,24 @@ pub fn add_header(headers: &mut Vec<Header>, header: Header) {
     headers.push(header);
 }
 
+/// The canonical casing of a header name: the first letter, and every letter
+/// after a hyphen, in uppercase and the rest in lowercase. `content-type`
+/// becomes `Content-Type`.
+pub(crate) fn canonical_name(name: &str) -> String {
+    let mut upper = true;
+    name.chars()
+        .map(|c| {
+            let c = if upper {
+                c.to_ascii_uppercase()
+            } else {
+                c.to_ascii_lowercase()
+            };
+            upper = c == '-';
+            c
+        })
+        .collect()
+}
+
 // https://tools.ietf.org/html/rfc7230#section-3.2
 // Each header field consists of a case-insensitive field name followed
 // by a colon (":"), optional leading whitespace, the field value, and
@@ -226,6 +244,27 @@ fn is_field_vchar_or_obs_fold(b: &u8) -> bool {
     }
 }
 
+/// Whether the value is a plausible language-tag list for `Accept-Language`,
+/// like `da, en-GB;q=0.8, *;q=0.1`.
+pub(crate) fn is_language_list(value: &str) -> bool {
+    value.split(',').all(|item| {
+        let mut parts = item.split(';').map(str::trim);
+        let tag = parts.next().unwrap_or("");
+        let valid_tag = tag == "*"
+            || tag.split('-').all(|sub| {
+                (1..=8).contains(&sub.len()) && sub.bytes().all(|b| b.is_ascii_alphanumeric())
+            });
+        let valid_weights = parts.all(|param| {
+            param
+                .strip_prefix("q=")
+                .and_then(|q| q.parse::<f32>().ok())
+                .map(|q| (0.0..=1.0).contains(&q))
+                .unwrap_or(false)
+        });
+        valid_tag && valid_weights
+    })
+}
+
 impl FromStr for Header {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -243,6 +282,14 @@ impl FromStr for Header {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_canonical_name() {
+        assert_eq!(canonical_name("content-type"), "Content-Type");
+        assert_eq!(canonical_name("X-FORWARDED-FOR"), "X-Forwarded-For");
+        assert_eq!(canonical_name("dnt"), "Dnt");
+        assert_eq!(canonical_name("a--b"), "A--B");
+    }
+
     #[test]
     fn test_valid_name() {
         assert!(valid_name(b"example"));
@@ -268,6 +315,18 @@ mod tests {
         assert!(!valid_value(b"foo\x7F"));
     }
 
+    #[test]
+    fn test_is_language_list() {
+        assert!(is_language_list("da"));
+        assert!(is_language_list("da, en-GB;q=0.8, en;q=0.7"));
+        assert!(is_language_list("zh-Hant-TW,*;q=0.1"));
+        assert!(!is_language_list(""));
+        assert!(!is_language_list("en,,fr"));
+        assert!(!is_language_list("en;q=2"));
+        assert!(!is_language_list("en_US"));
+        assert!(!is_language_list("verylongtag"));
+    }
+
     #[test]
     fn test_parse_invalid_name() {
         let cases = vec![
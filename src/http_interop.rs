@@ -27,12 +27,17 @@ impl<T: AsRef<[u8]> + Send + Sync + 'static> From<http::Response<T>> for Respons
         let version_str = format!("{:?}", value.version());
         let status_line = format!("{} {}", version_str, value.status());
         let status_num = u16::from(value.status());
+        let content_encoding = value
+            .headers()
+            .get("content-encoding")
+            .and_then(|v| v.to_str().ok())
+            .map(String::from);
         Response {
             url: "https://example.com/".parse().unwrap(),
             status_line,
             index: ResponseStatusIndex {
                 http_version: version_str.len(),
-                response_code: version_str.len() + status_num.to_string().len(),
+                response_code: version_str.len() + 1 + status_num.to_string().len(),
             },
             status: status_num,
             headers: value
@@ -47,9 +52,18 @@ impl<T: AsRef<[u8]> + Send + Sync + 'static> From<http::Response<T>> for Respons
                 })
                 .collect::<Vec<_>>(),
             reader: Box::new(Cursor::new(value.into_body())),
+            pending: None,
             remote_addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 80),
             local_addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0),
+            alpn_protocol: None,
+            head_request: false,
             history: vec![],
+            content_encoding,
+            remaining: None,
+            #[cfg(feature = "charset")]
+            charset_sniffing: false,
+            #[cfg(feature = "charset")]
+            force_charset: None,
         }
     }
 }
@@ -81,6 +95,34 @@ fn create_builder(response: &Response) -> http::response::Builder {
     response_builder
 }
 
+impl Response {
+    /// Copy the headers of this response into an [http::HeaderMap], without
+    /// consuming the response.
+    ///
+    /// Headers are appended, so repeated headers keep all their values. Headers
+    /// whose name or value isn't valid for an [http::HeaderMap] are skipped.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["http-interop"] }`
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// # ureq::is_test(true);
+    /// let response = ureq::get("http://example.com").call()?;
+    /// let mut headers = http::HeaderMap::new();
+    /// response.copy_headers_to(&mut headers);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn copy_headers_to(&self, map: &mut http::HeaderMap) {
+        for header in &self.headers {
+            let name = http::HeaderName::from_bytes(header.name().as_bytes());
+            let value = http::HeaderValue::from_bytes(header.value_raw());
+            if let (Ok(name), Ok(value)) = (name, value) {
+                map.append(name, value);
+            }
+        }
+    }
+}
+
 /// Converts a [Response](crate::Response) into an [http::Response], where the
 /// body is a reader containing the body of the response.
 ///
@@ -324,13 +366,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn copy_headers_to_header_map() {
+        let mut response = super::Response::new(200, "OK", "").unwrap();
+        response
+            .headers
+            .push(crate::Header::new("Content-Type", "text/plain"));
+        response.headers.push(crate::Header::new("X-Custom", "1"));
+        response.headers.push(crate::Header::new("Bad Name", "2"));
+
+        let mut map = http::HeaderMap::new();
+        response.copy_headers_to(&mut map);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get("content-type").unwrap(), "text/plain");
+        assert_eq!(map.get("x-custom").unwrap(), "1");
+    }
+
     #[test]
     fn convert_to_http_response_bytes() {
         use http::Response;
         use std::io::{Cursor, Read};
 
         let mut response = super::Response::new(200, "OK", "tbr").unwrap();
-        response.reader = Box::new(Cursor::new(vec![0xde, 0xad, 0xbe, 0xef]));
+        response.set_reader(Box::new(Cursor::new(vec![0xde, 0xad, 0xbe, 0xef])));
         let http_response: Response<Box<dyn Read + Send + Sync + 'static>> = response.into();
 
         let mut buf = vec![];
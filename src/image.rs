@@ -0,0 +1,188 @@
+//! Reading image dimensions from the first bytes of PNG, GIF, JPEG and WebP files.
+
+/// The outcome of looking for the dimensions in the start of an image.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Dimensions {
+    /// Width and height in pixels.
+    Found(u32, u32),
+    /// The bytes are the start of a known format, but too short to tell.
+    NeedMore,
+    /// The bytes aren't an image format we know, or the image is broken.
+    Unknown,
+}
+
+use Dimensions::*;
+
+pub(crate) fn dimensions(buf: &[u8]) -> Dimensions {
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+    if starts_with(buf, PNG) {
+        png(buf)
+    } else if starts_with(buf, b"GIF87a") || starts_with(buf, b"GIF89a") {
+        gif(buf)
+    } else if starts_with(buf, b"\xff\xd8") {
+        jpeg(buf)
+    } else if starts_with(buf, b"RIFF") && (buf.len() < 12 || &buf[8..12] == b"WEBP") {
+        webp(buf)
+    } else {
+        Unknown
+    }
+}
+
+/// Like `buf.starts_with(prefix)`, but also true while `buf` is a shorter
+/// prefix of `prefix`.
+fn starts_with(buf: &[u8], prefix: &[u8]) -> bool {
+    let n = buf.len().min(prefix.len());
+    buf[..n] == prefix[..n]
+}
+
+fn u16_be(b: &[u8]) -> u32 {
+    u16::from_be_bytes([b[0], b[1]]) as u32
+}
+
+fn u16_le(b: &[u8]) -> u32 {
+    u16::from_le_bytes([b[0], b[1]]) as u32
+}
+
+fn u24_le(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], 0])
+}
+
+// The IHDR chunk is always first, with width and height as its first fields.
+fn png(buf: &[u8]) -> Dimensions {
+    if buf.len() < 24 {
+        return NeedMore;
+    }
+    if &buf[12..16] != b"IHDR" {
+        return Unknown;
+    }
+    let width = u32::from_be_bytes([buf[16], buf[17], buf[18], buf[19]]);
+    let height = u32::from_be_bytes([buf[20], buf[21], buf[22], buf[23]]);
+    Found(width, height)
+}
+
+// The logical screen descriptor follows the signature.
+fn gif(buf: &[u8]) -> Dimensions {
+    if buf.len() < 10 {
+        return NeedMore;
+    }
+    Found(u16_le(&buf[6..]), u16_le(&buf[8..]))
+}
+
+// Walk the segments until a start of frame segment.
+fn jpeg(buf: &[u8]) -> Dimensions {
+    let mut pos = 2;
+    loop {
+        // Markers may be preceded by any number of fill bytes.
+        while buf.get(pos) == Some(&0xff) && buf.get(pos + 1) == Some(&0xff) {
+            pos += 1;
+        }
+        if buf.len() < pos + 4 {
+            return NeedMore;
+        }
+        if buf[pos] != 0xff {
+            return Unknown;
+        }
+        let marker = buf[pos + 1];
+        // Standalone markers have no length.
+        if marker == 0x01 || (0xd0..=0xd7).contains(&marker) {
+            pos += 2;
+            continue;
+        }
+        let len = u16_be(&buf[pos + 2..]) as usize;
+        if len < 2 {
+            return Unknown;
+        }
+        let is_sof = (0xc0..=0xcf).contains(&marker) && ![0xc4, 0xc8, 0xcc].contains(&marker);
+        if is_sof {
+            // length (2), precision (1), height (2), width (2)
+            if buf.len() < pos + 9 {
+                return NeedMore;
+            }
+            let height = u16_be(&buf[pos + 5..]);
+            let width = u16_be(&buf[pos + 7..]);
+            return Found(width, height);
+        }
+        if marker == 0xd9 || marker == 0xda {
+            // End of image or start of scan before any frame header.
+            return Unknown;
+        }
+        pos += 2 + len;
+    }
+}
+
+// The first chunk is one of VP8 (lossy), VP8L (lossless) or VP8X (extended).
+fn webp(buf: &[u8]) -> Dimensions {
+    if buf.len() < 30 {
+        return NeedMore;
+    }
+    match &buf[12..16] {
+        b"VP8 " => {
+            if &buf[23..26] != b"\x9d\x01\x2a" {
+                return Unknown;
+            }
+            Found(u16_le(&buf[26..]) & 0x3fff, u16_le(&buf[28..]) & 0x3fff)
+        }
+        b"VP8L" => {
+            if buf[20] != 0x2f {
+                return Unknown;
+            }
+            let bits = u32::from_le_bytes([buf[21], buf[22], buf[23], buf[24]]);
+            Found((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1)
+        }
+        b"VP8X" => Found(u24_le(&buf[24..]) + 1, u24_le(&buf[27..]) + 1),
+        _ => Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn png_dimensions() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&640_u32.to_be_bytes());
+        png.extend_from_slice(&480_u32.to_be_bytes());
+        assert_eq!(dimensions(&png[..10]), NeedMore);
+        assert_eq!(dimensions(&png), Found(640, 480));
+    }
+
+    #[test]
+    fn gif_dimensions() {
+        assert_eq!(dimensions(b"GIF89a\x20\x01\x10\x00"), Found(288, 16));
+        assert_eq!(dimensions(b"GIF8"), NeedMore);
+    }
+
+    #[test]
+    fn jpeg_dimensions() {
+        let jpeg = b"\xff\xd8\
+            \xff\xe0\x00\x10JFIF\x00\x01\x01\x00\x00\x01\x00\x01\x00\x00\
+            \xff\xc0\x00\x11\x08\x01\xe0\x02\x80\x03";
+        assert_eq!(dimensions(jpeg), Found(640, 480));
+        assert_eq!(dimensions(&jpeg[..22]), NeedMore);
+    }
+
+    #[test]
+    fn webp_dimensions() {
+        let mut vp8x = b"RIFF\0\0\0\0WEBPVP8X\x0a\0\0\0\0\0\0\0".to_vec();
+        vp8x.extend_from_slice(&[0x7f, 0x02, 0x00, 0xdf, 0x01, 0x00]);
+        assert_eq!(dimensions(&vp8x), Found(640, 480));
+
+        let mut vp8 = b"RIFF\0\0\0\0WEBPVP8 \0\0\0\0\0\0\0\x9d\x01\x2a".to_vec();
+        vp8.extend_from_slice(&[0x80, 0x02, 0xe0, 0x01]);
+        assert_eq!(dimensions(&vp8), Found(640, 480));
+
+        let mut vp8l = b"RIFF\0\0\0\0WEBPVP8L\0\0\0\0\x2f".to_vec();
+        let bits: u32 = 639 | (479 << 14);
+        vp8l.extend_from_slice(&bits.to_le_bytes());
+        vp8l.resize(30, 0);
+        assert_eq!(dimensions(&vp8l), Found(640, 480));
+    }
+
+    #[test]
+    fn not_an_image() {
+        assert_eq!(dimensions(b"<html>"), Unknown);
+        assert_eq!(dimensions(b"RIFF\0\0\0\0WAVE"), Unknown);
+    }
+}
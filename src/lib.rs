@@ -161,6 +161,7 @@
 //! * `gzip` enables requests of gzip-compressed responses and decompresses them. This is enabled by default.
 //! * `brotli` enables requests brotli-compressed responses and decompresses them.
 //! * `http-interop` enables conversion methods to and from `http::Response` and `http::request::Builder`.
+//! * `serde` implements `serde::Serialize` for [Error], for structured logging.
 //!
 //! # Plain requests
 //!
@@ -350,10 +351,16 @@
 //!
 
 mod agent;
+mod auth;
+mod backoff;
 mod body;
+mod checksum;
 mod chunked;
+mod dns;
 mod error;
+mod hash;
 mod header;
+mod image;
 mod middleware;
 mod pool;
 mod proxy;
@@ -361,6 +368,7 @@ mod request;
 mod resolve;
 mod response;
 mod stream;
+mod throttle;
 mod unit;
 
 // rustls is our default tls engine. If the feature is on, it will be
@@ -419,14 +427,17 @@ mod http_interop;
 pub use crate::agent::Agent;
 pub use crate::agent::AgentBuilder;
 pub use crate::agent::RedirectAuthHeaders;
+pub use crate::auth::AuthChallenge;
+pub use crate::backoff::BackoffPolicy;
+pub use crate::checksum::Checksum;
 pub use crate::error::{Error, ErrorKind, OrAnyStatus, Transport};
 pub use crate::header::Header;
 pub use crate::middleware::{Middleware, MiddlewareNext};
 pub use crate::proxy::Proxy;
-pub use crate::request::{Request, RequestUrl};
-pub use crate::resolve::Resolver;
-pub use crate::response::Response;
-pub use crate::stream::{ReadWrite, TlsConnector};
+pub use crate::request::{CallHandle, Request, RequestRecording, RequestUrl};
+pub use crate::resolve::{IpFamily, Resolver};
+pub use crate::response::{Response, ResponseHead};
+pub use crate::stream::{Direction, ReadWrite, TlsConnector};
 
 // re-export
 #[cfg(feature = "cookies")]
@@ -1,7 +1,8 @@
 use std::collections::hash_map::Entry;
 use std::collections::{HashMap, VecDeque};
 use std::io::{self, Read};
-use std::sync::Mutex;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
 
 use crate::agent::AgentState;
 use crate::stream::Stream;
@@ -32,21 +33,54 @@ use url::Url;
 ///  - The length of `lru` is less than or equal to max_idle_connections.
 ///  - The length of recycle[K] is less than or equal to max_idle_connections_per_host.
 ///
+/// If an idle timeout is set, streams that have been in the pool for longer than it
+/// are dropped when a stream for the same key is requested. Every `idle_check_interval`
+/// a request also sweeps the whole pool for such streams, so connections to hosts that
+/// are rarely used don't linger.
+///
 /// *Internal API*
 pub(crate) struct ConnectionPool {
     inner: Mutex<Inner>,
     max_idle_connections: usize,
     max_idle_connections_per_host: usize,
+    idle_timeout: Option<Duration>,
+    idle_check_interval: Duration,
+    // Source of the current time, replaceable in tests.
+    now: Box<dyn Fn() -> Instant + Send + Sync>,
 }
 
 struct Inner {
-    // the actual pooled connection. however only one per hostname:port.
-    recycle: HashMap<PoolKey, VecDeque<Stream>>,
+    // the actual pooled connection, with the time it was added to the pool.
+    recycle: HashMap<PoolKey, VecDeque<(Stream, Instant)>>,
     // This is used to keep track of which streams to expire when the
     // pool reaches MAX_IDLE_CONNECTIONS. The corresponding PoolKeys for
     // recently used Streams are added to the back of the queue;
     // old streams are removed from the front.
     lru: VecDeque<PoolKey>,
+    // When the whole pool was last checked for idle streams.
+    last_sweep: Instant,
+}
+
+impl Inner {
+    /// Drop the streams for `key` that have been idle for longer than `timeout`.
+    fn expire(&mut self, key: &PoolKey, now: Instant, timeout: Duration) {
+        if let Entry::Occupied(mut occupied_entry) = self.recycle.entry(key.clone()) {
+            let streams = occupied_entry.get_mut();
+            // Streams are ordered oldest first.
+            while let Some((_, added)) = streams.front() {
+                if now.saturating_duration_since(*added) <= timeout {
+                    break;
+                }
+                let (stream, _) = streams.pop_front().unwrap();
+                debug!("dropping idle stream from pool: {:?} -> {:?}", key, stream);
+                remove_first_match(&mut self.lru, key)
+                    .expect("invariant failed: key in recycle but not in lru");
+            }
+            if streams.is_empty() {
+                occupied_entry.remove();
+            }
+        }
+    }
 }
 
 impl fmt::Debug for ConnectionPool {
@@ -81,14 +115,28 @@ impl ConnectionPool {
             inner: Mutex::new(Inner {
                 recycle: HashMap::new(),
                 lru: VecDeque::new(),
+                last_sweep: Instant::now(),
             }),
             max_idle_connections,
             max_idle_connections_per_host,
+            idle_timeout: None,
+            idle_check_interval: Duration::from_secs(60),
+            now: Box::new(Instant::now),
         }
     }
 
+    pub(crate) fn with_idle_timeout(
+        mut self,
+        idle_timeout: Option<Duration>,
+        idle_check_interval: Duration,
+    ) -> Self {
+        self.idle_timeout = idle_timeout;
+        self.idle_check_interval = idle_check_interval;
+        self
+    }
+
     /// Return true if either of the max_* settings is 0, meaning we should do no work.
-    fn noop(&self) -> bool {
+    pub(crate) fn noop(&self) -> bool {
         self.max_idle_connections == 0 || self.max_idle_connections_per_host == 0
     }
 
@@ -100,26 +148,49 @@ impl ConnectionPool {
 
     fn remove(&self, key: &PoolKey) -> Option<Stream> {
         let mut inner = self.inner.lock().unwrap();
-        match inner.recycle.entry(key.clone()) {
-            Entry::Occupied(mut occupied_entry) => {
-                let streams = occupied_entry.get_mut();
-                // Take the newest stream.
-                let stream = streams.pop_back();
-                let stream = stream.expect("invariant failed: empty VecDeque in `recycle`");
-
-                if streams.is_empty() {
-                    occupied_entry.remove();
+        let now = (self.now)();
+        if let Some(timeout) = self.idle_timeout {
+            if now.saturating_duration_since(inner.last_sweep) >= self.idle_check_interval {
+                let keys: Vec<PoolKey> = inner.recycle.keys().cloned().collect();
+                for k in &keys {
+                    inner.expire(k, now, timeout);
                 }
-
-                // Remove the newest matching PoolKey from self.lru. That
-                // corresponds to the stream we just removed from `recycle`.
-                remove_last_match(&mut inner.lru, key)
-                    .expect("invariant failed: key in recycle but not in lru");
-
-                debug!("pulling stream from pool: {:?} -> {:?}", key, stream);
-                Some(stream)
+                inner.last_sweep = now;
+            } else {
+                inner.expire(key, now, timeout);
             }
-            Entry::Vacant(_) => None,
+        }
+        loop {
+            let (stream, added) = match inner.recycle.entry(key.clone()) {
+                Entry::Occupied(mut occupied_entry) => {
+                    let streams = occupied_entry.get_mut();
+                    // Take the newest stream.
+                    let stream = streams.pop_back();
+                    let stream = stream.expect("invariant failed: empty VecDeque in `recycle`");
+
+                    if streams.is_empty() {
+                        occupied_entry.remove();
+                    }
+                    stream
+                }
+                Entry::Vacant(_) => return None,
+            };
+
+            // Remove the newest matching PoolKey from self.lru. That
+            // corresponds to the stream we just removed from `recycle`.
+            remove_last_match(&mut inner.lru, key)
+                .expect("invariant failed: key in recycle but not in lru");
+
+            if stream.keep_alive_expired(now.saturating_duration_since(added)) {
+                debug!(
+                    "dropping stream from pool; past keep-alive timeout: {:?} -> {:?}",
+                    key, stream
+                );
+                continue;
+            }
+
+            debug!("pulling stream from pool: {:?} -> {:?}", key, stream);
+            return Some(stream);
         }
     }
 
@@ -129,14 +200,15 @@ impl ConnectionPool {
         }
         debug!("adding stream to pool: {:?} -> {:?}", key, stream);
 
+        let now = (self.now)();
         let mut inner = self.inner.lock().unwrap();
         match inner.recycle.entry(key.clone()) {
             Entry::Occupied(mut occupied_entry) => {
                 let streams = occupied_entry.get_mut();
-                streams.push_back(stream);
+                streams.push_back((stream, now));
                 if streams.len() > self.max_idle_connections_per_host {
                     // Remove the oldest entry
-                    let stream = streams.pop_front().expect("empty streams list");
+                    let (stream, _) = streams.pop_front().expect("empty streams list");
                     debug!(
                         "host {:?} has {} conns, dropping oldest: {:?}",
                         key,
@@ -148,7 +220,7 @@ impl ConnectionPool {
                 }
             }
             Entry::Vacant(vacant_entry) => {
-                vacant_entry.insert(vec![stream].into());
+                vacant_entry.insert(vec![(stream, now)].into());
             }
         }
         inner.lru.push_back(key.clone());
@@ -168,7 +240,7 @@ impl ConnectionPool {
         match inner.recycle.entry(key) {
             Entry::Occupied(mut occupied_entry) => {
                 let streams = occupied_entry.get_mut();
-                let stream = streams
+                let (stream, _) = streams
                     .pop_front()
                     .expect("invariant failed: key existed in recycle but no streams available");
                 debug!("dropping oldest stream in pool: {:?}", stream);
@@ -180,6 +252,15 @@ impl ConnectionPool {
         }
     }
 
+    /// Close the oldest stream in the pool, if there is any.
+    pub(crate) fn close_oldest(&self) -> bool {
+        if self.noop() || self.inner.lock().unwrap().lru.is_empty() {
+            return false;
+        }
+        self.remove_oldest();
+        true
+    }
+
     #[cfg(test)]
     pub fn len(&self) -> usize {
         self.inner.lock().unwrap().lru.len()
@@ -253,11 +334,95 @@ impl PoolReturner {
         if let Some((weak_state, pool_key)) = &self.inner {
             if let Some(state) = weak_state.upgrade() {
                 state.pool.add(pool_key, stream);
+                if let Some(limit) = &state.connection_limit {
+                    limit.notify();
+                }
             }
         }
     }
 }
 
+/// Caps the number of open connections of an agent, whether they are in use
+/// or idle in the pool. Each open connection holds a [`ConnectionPermit`].
+///
+/// *Internal API*
+pub(crate) struct ConnectionLimit {
+    max: usize,
+    state: Mutex<LimitState>,
+    changed: Condvar,
+}
+
+struct LimitState {
+    open: usize,
+    // Bumped when a permit is released or a stream is returned to the pool,
+    // so that waiters don't miss a change between checking and waiting.
+    changes: u64,
+}
+
+impl ConnectionLimit {
+    pub(crate) fn new(max: usize) -> Self {
+        ConnectionLimit {
+            max,
+            state: Mutex::new(LimitState {
+                open: 0,
+                changes: 0,
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    pub(crate) fn max(&self) -> usize {
+        self.max
+    }
+
+    /// Take a permit if the limit allows another connection. Otherwise return the
+    /// current change count to pass to `wait`.
+    pub(crate) fn try_acquire(self: &Arc<Self>) -> Result<ConnectionPermit, u64> {
+        let mut state = self.state.lock().unwrap();
+        if state.open >= self.max {
+            return Err(state.changes);
+        }
+        state.open += 1;
+        Ok(ConnectionPermit(self.clone()))
+    }
+
+    /// Wait for a permit to be released or a stream to be returned to the pool
+    /// after `seen`. Returns false if none happened before `deadline`.
+    pub(crate) fn wait(&self, seen: u64, deadline: Option<Instant>) -> bool {
+        let mut state = self.state.lock().unwrap();
+        while state.changes == seen {
+            state = match deadline {
+                None => self.changed.wait(state).unwrap(),
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(left) if !left.is_zero() => {
+                        self.changed.wait_timeout(state, left).unwrap().0
+                    }
+                    _ => return false,
+                },
+            };
+        }
+        true
+    }
+
+    /// Wake up the waiters, for instance because a stream was returned to the pool.
+    pub(crate) fn notify(&self) {
+        self.state.lock().unwrap().changes += 1;
+        self.changed.notify_all();
+    }
+}
+
+/// Counts an open connection against a [`ConnectionLimit`] until dropped.
+///
+/// *Internal API*
+pub(crate) struct ConnectionPermit(Arc<ConnectionLimit>);
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        self.0.state.lock().unwrap().open -= 1;
+        self.0.notify();
+    }
+}
+
 /// Read wrapper that returns a stream to the pool once the
 /// read is exhausted (reached a 0).
 ///
@@ -402,6 +567,64 @@ mod tests {
         assert_eq!(pool.len(), 0);
     }
 
+    #[test]
+    fn pool_idle_timeout() {
+        use std::sync::Arc;
+
+        let start = Instant::now();
+        let clock = Arc::new(Mutex::new(start));
+        let clock2 = clock.clone();
+        let mut pool = ConnectionPool::new_with_limits(10, 2)
+            .with_idle_timeout(Some(Duration::from_secs(60)), Duration::from_secs(10));
+        pool.now = Box::new(move || *clock2.lock().unwrap());
+        let advance = |secs| *clock.lock().unwrap() = start + Duration::from_secs(secs);
+        let key = |hostname: &str| PoolKey::from_parts("https", hostname, 999);
+
+        pool.add(&key("old"), NoopStream::stream(PoolReturner::none()));
+        advance(15);
+        pool.add(&key("new"), NoopStream::stream(PoolReturner::none()));
+        pool.add(&key("new"), NoopStream::stream(PoolReturner::none()));
+        assert_eq!(pool.len(), 3);
+
+        // A sweep, triggered by a request for any key, drops the old stream.
+        advance(70);
+        assert!(pool.remove(&key("other")).is_none());
+        assert_eq!(pool.len(), 2);
+
+        // Between sweeps, only the requested key is checked.
+        pool.add(&key("old"), NoopStream::stream(PoolReturner::none()));
+        advance(79);
+        assert!(pool.remove(&key("other")).is_none());
+        assert_eq!(pool.len(), 3);
+        assert!(pool.remove(&key("new")).is_none());
+        assert_eq!(pool.len(), 1);
+        assert!(pool.remove(&key("old")).is_some());
+        assert_eq!(pool.len(), 0);
+    }
+
+    #[test]
+    fn pool_keep_alive_timeout() {
+        use std::sync::Arc;
+
+        let start = Instant::now();
+        let clock = Arc::new(Mutex::new(start));
+        let clock2 = clock.clone();
+        let mut pool = ConnectionPool::new_with_limits(10, 2);
+        pool.now = Box::new(move || *clock2.lock().unwrap());
+        let key = PoolKey::from_parts("https", "example.com", 999);
+
+        let mut stream = NoopStream::stream(PoolReturner::none());
+        stream.set_keep_alive_timeout(Some(Duration::from_secs(5)));
+        pool.add(&key, stream);
+        pool.add(&key, NoopStream::stream(PoolReturner::none()));
+        *clock.lock().unwrap() = start + Duration::from_secs(5);
+
+        // The stream without a keep-alive timeout is still good.
+        assert!(pool.remove(&key).is_some());
+        assert!(pool.remove(&key).is_none());
+        assert_eq!(pool.len(), 0);
+    }
+
     #[test]
     fn pool_checks_proxy() {
         // Test inserting different poolkeys with same address but different proxies.
@@ -175,9 +175,9 @@ Proxy-Connection: Keep-Alive\r\n\
             .ok_or_else(|| ErrorKind::ProxyConnect.new())?;
 
         match status_code {
-            "200" => Ok(()),
             "401" | "407" => Err(ErrorKind::ProxyUnauthorized.new()),
-            _ => Err(ErrorKind::ProxyConnect.new()),
+            s if s.len() == 3 && s.starts_with('2') => Ok(()),
+            _ => Err(ErrorKind::ProxyConnect.msg(format!("proxy responded with {}", status_code))),
         }
     }
 }
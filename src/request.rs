@@ -1,18 +1,28 @@
-use std::io::Read;
-use std::{fmt, time};
+use std::cell::RefCell;
+use std::io::{Read, Seek};
+use std::sync::{mpsc, Arc};
+use std::{fmt, thread, time};
 
 use url::{form_urlencoded, ParseError, Url};
 
 use crate::agent::Agent;
-use crate::body::Payload;
+use crate::auth::DigestAuth;
+#[cfg(feature = "json")]
+use crate::body::NdjsonReader;
+use crate::body::{BodySize, Payload};
+use crate::checksum::Checksum;
 use crate::error::{Error, ErrorKind};
 use crate::header::{self, Header};
 use crate::middleware::MiddlewareNext;
+use crate::stream::{io_err_timeout, Direction, Tap};
 use crate::unit::{self, Unit};
 use crate::Response;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// The largest body buffered to compute the `Content-MD5` header.
+const CONTENT_MD5_MAX_SIZE: u64 = 10 * 1024 * 1024;
+
 /// Request instances are builders that creates a request.
 ///
 /// ```
@@ -32,6 +42,17 @@ pub struct Request {
     url: String,
     pub(crate) headers: Vec<Header>,
     timeout: Option<time::Duration>,
+    deadline: Option<time::Instant>,
+    digest_auth: Option<DigestAuth>,
+    simple_request: bool,
+    method_override: bool,
+    rate_limit: Option<u64>,
+    allow_conflicting_length_encoding: bool,
+    content_md5: bool,
+    tap: Option<Tap>,
+    #[cfg(feature = "charset")]
+    force_charset: Option<String>,
+    body_preview: Option<Vec<u8>>,
 }
 
 impl fmt::Debug for Request {
@@ -52,6 +73,17 @@ impl Request {
             url,
             headers: agent.state.headers.clone(),
             timeout: None,
+            deadline: None,
+            digest_auth: None,
+            simple_request: false,
+            method_override: false,
+            rate_limit: None,
+            allow_conflicting_length_encoding: false,
+            content_md5: false,
+            tap: None,
+            #[cfg(feature = "charset")]
+            force_charset: None,
+            body_preview: None,
         }
     }
 
@@ -62,6 +94,202 @@ impl Request {
         self
     }
 
+    /// Sets an instant the whole request must be done by, including redirects and
+    /// reading the body.
+    ///
+    /// If a timeout is set too, the request must be done by whichever comes first.
+    /// A deadline that has already passed fails the request before connecting.
+    ///
+    /// ```
+    /// use std::time::{Duration, Instant};
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// # ureq::is_test(true);
+    /// let deadline = Instant::now() + Duration::from_secs(10);
+    /// let resp = ureq::get("http://httpbin.org/get")
+    ///     .deadline(deadline)
+    ///     .call()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn deadline(mut self, when: time::Instant) -> Self {
+        self.deadline = Some(when);
+        self
+    }
+
+    /// Use HTTP Digest authentication with the given credentials.
+    ///
+    /// If the server answers `401 Unauthorized` with a `Digest` challenge
+    /// in `WWW-Authenticate`, the request is retried once with an `Authorization`
+    /// header answering that challenge. Supports the `MD5` and `SHA-256` algorithms
+    /// (and their `-sess` variants) with `qop=auth`.
+    ///
+    /// Requests with a body are not retried, since the body can't be sent twice.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// # ureq::is_test(true);
+    /// let resp = ureq::get("http://httpbin.org/digest-auth/auth/user/passwd")
+    ///     .auth_digest("user", "passwd")
+    ///     .call()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn auth_digest(mut self, username: &str, password: &str) -> Self {
+        self.digest_auth = Some(DigestAuth::new(username, password));
+        self
+    }
+
+    /// Send the request HTTP/0.9 style, for very old or minimal servers.
+    ///
+    /// The request is only the line `METHOD path`, without an HTTP version or
+    /// any headers. The server is expected to answer with the body directly,
+    /// without a status line, and close the connection when done. The response
+    /// is presented as `HTTP/0.9 200 OK` with no headers.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// # ureq::is_test(true);
+    /// let resp = ureq::get("http://example.com/")
+    ///     .simple_request(true)
+    ///     .call()?;
+    /// assert_eq!(resp.http_version(), "HTTP/0.9");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn simple_request(mut self, enabled: bool) -> Self {
+        self.simple_request = enabled;
+        self
+    }
+
+    /// Send PUT, PATCH and DELETE requests as POST with an `X-HTTP-Method-Override` header.
+    ///
+    /// This is for servers behind proxies that only let GET and POST through. The server
+    /// side framework is expected to route the request by the `X-HTTP-Method-Override`
+    /// header instead of the request line. Other methods are sent as they are.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// # ureq::is_test(true);
+    /// let resp = ureq::delete("http://httpbin.org/delete")
+    ///     .method_override(true)
+    ///     .call()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn method_override(mut self, enabled: bool) -> Self {
+        self.method_override = enabled;
+        self
+    }
+
+    /// Decode the response in [`Response::into_string`] with `charset`, whatever
+    /// the `Content-Type` header declares.
+    ///
+    /// This corrects servers that label their content with the wrong charset.
+    /// Labels that aren't known are ignored.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["charset"] }`
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// # ureq::is_test(true);
+    /// let text = ureq::get("http://example.com/")
+    ///     .force_charset("shift_jis")
+    ///     .call()?
+    ///     .into_string()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "charset")]
+    pub fn force_charset(mut self, charset: &str) -> Self {
+        self.force_charset = Some(charset.to_string());
+        self
+    }
+
+    /// Limit the upload of the request body and the download of the response
+    /// body to an average of `bytes_per_sec`.
+    ///
+    /// Short bursts over the limit are allowed after the transfer has been idle,
+    /// but never more than a tenth of a second's worth of bytes.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// # ureq::is_test(true);
+    /// let resp = ureq::get("http://example.com/")
+    ///     .rate_limit(100 * 1024)
+    ///     .call()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn rate_limit(mut self, bytes_per_sec: u64) -> Self {
+        self.rate_limit = Some(bytes_per_sec);
+        self
+    }
+
+    /// Send a `Content-Length` header along with a chunked `Transfer-Encoding`.
+    ///
+    /// A request must not have both, so by default ureq doesn't add a `Content-Length`
+    /// header to requests with a chunked `Transfer-Encoding`. Enabling this adds it
+    /// for bodies of known size, which is only useful for testing how servers handle
+    /// such requests. The body is still sent chunked. Headers set with
+    /// [`Request::set`] are always sent as set.
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// let resp = ureq::post("http://example.com/")
+    ///     .allow_conflicting_length_encoding(true)
+    ///     .set("Transfer-Encoding", "chunked")
+    ///     .send_string("hello")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn allow_conflicting_length_encoding(mut self, allow: bool) -> Self {
+        self.allow_conflicting_length_encoding = allow;
+        self
+    }
+
+    /// Show every read and write on the connection to `tap`, for debugging.
+    ///
+    /// The bytes are those of HTTP, so for `https` they are shown before they
+    /// are encrypted and after they are decrypted. Reads are shown as they come
+    /// from the connection, which may be ahead of what was read of the body.
+    ///
+    /// ```
+    /// use ureq::Direction;
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// # ureq::is_test(true);
+    /// let resp = ureq::get("http://example.com/")
+    ///     .tap(|direction, bytes| match direction {
+    ///         Direction::Write => eprint!("> {}", String::from_utf8_lossy(bytes)),
+    ///         Direction::Read => eprint!("< {}", String::from_utf8_lossy(bytes)),
+    ///     })
+    ///     .call()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn tap(mut self, tap: impl Fn(Direction, &[u8]) + Send + Sync + 'static) -> Self {
+        self.tap = Some(Arc::new(tap));
+        self
+    }
+
+    /// Send a `Content-MD5` header with the base64 MD5 digest of the body.
+    ///
+    /// Bodies from a reader are buffered in memory to compute the digest,
+    /// and the request fails if they are larger than 10 MiB. A `Content-MD5`
+    /// header set on the request is left as is.
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// let resp = ureq::put("http://example.com/bucket/key")
+    ///     .with_content_md5()
+    ///     .send_string("hello")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_content_md5(mut self) -> Self {
+        self.content_md5 = true;
+        self
+    }
+
     /// Sends the request with no body and blocks the caller until done.
     ///
     /// Use this with GET, HEAD, OPTIONS or TRACE. It sends neither
@@ -79,6 +307,44 @@ impl Request {
         self.do_call(Payload::Empty)
     }
 
+    /// Sends the request like [`Request::call`] and passes the response to `f`.
+    ///
+    /// Errors, including [`Error::Status`] for 4xx and 5xx responses, are returned
+    /// without calling `f`.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// # ureq::is_test(true);
+    /// let length = ureq::get("http://example.com/")
+    ///     .call_map(|resp| Ok(resp.into_string()?.len()))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn call_map<T>(self, f: impl FnOnce(Response) -> Result<T>) -> Result<T> {
+        f(self.call()?)
+    }
+
+    /// Sends the request with an empty body and `Content-Length: 0`.
+    ///
+    /// Use this with POST, PUT or PATCH requests that have no body, for servers that
+    /// require the length to be stated. Unlike [`Request::call`], the request has
+    /// explicit body framing.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// # ureq::is_test(true);
+    /// let resp = ureq::post("http://example.com/")
+    ///     .send_empty()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn send_empty(mut self) -> Result<Response> {
+        if self.header("content-length").is_none() {
+            self.headers.push(Header::new("Content-Length", "0"));
+        }
+        self.do_call(Payload::Empty)
+    }
+
     fn parse_url(&self) -> Result<Url> {
         Ok(self.url.parse().and_then(|url: Url|
             // No hostname is fine for urls in general, but not for website urls.
@@ -90,6 +356,24 @@ impl Request {
         )?)
     }
 
+    /// Append the query parameters configured on the agent, unless the request
+    /// already has a parameter of that name.
+    fn add_agent_query_params(&self, url: &mut Url) {
+        let missing: Vec<_> = self
+            .agent
+            .state
+            .query_params
+            .iter()
+            .filter(|(param, _)| !url.query_pairs().any(|(name, _)| name == param.as_str()))
+            .collect();
+        if !missing.is_empty() {
+            let mut query_pairs = url.query_pairs_mut();
+            for (param, value) in missing {
+                query_pairs.append_pair(param, value);
+            }
+        }
+    }
+
     /// Add Accept-Encoding header with supported values, unless user has
     /// already set this header or is requesting a specific byte-range.
     #[cfg(any(feature = "gzip", feature = "brotli"))]
@@ -110,16 +394,41 @@ impl Request {
         }
     }
 
+    fn do_call(self, payload: Payload) -> Result<Response> {
+        let agent = self.agent.clone();
+        match &agent.state.trace_hooks {
+            Some(hooks) => {
+                let token = (hooks.start)(&self);
+                let result = self.do_call_untraced(payload);
+                (hooks.end)(token, result.as_ref());
+                result
+            }
+            None => self.do_call_untraced(payload),
+        }
+    }
+
     #[cfg_attr(not(any(feature = "gzip", feature = "brotli")), allow(unused_mut))]
-    fn do_call(mut self, payload: Payload) -> Result<Response> {
+    fn do_call_untraced(mut self, payload: Payload) -> Result<Response> {
         for h in &self.headers {
             h.validate()?;
         }
-        let url = self.parse_url()?;
+        if let Some(languages) = &self.agent.config.accept_language {
+            if !header::is_language_list(languages) {
+                return Err(
+                    ErrorKind::BadHeader.msg(format!("invalid Accept-Language '{}'", languages))
+                );
+            }
+        }
+        let mut url = self.parse_url()?;
+        self.add_agent_query_params(&mut url);
 
         #[cfg(any(feature = "gzip", feature = "brotli"))]
         self.add_accept_encoding();
 
+        if self.agent.config.record_request_bodies {
+            self.body_preview = payload.preview().map(|b| b.to_vec());
+        }
+
         let deadline = match self.timeout.or(self.agent.config.timeout) {
             None => None,
             Some(timeout) => {
@@ -135,19 +444,65 @@ impl Request {
                 }
             }
         };
+        let deadline = match (deadline, self.deadline) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        if matches!(deadline, Some(dl) if dl <= time::Instant::now()) {
+            return Err(io_err_timeout("request deadline has passed".to_string()).into());
+        }
 
         let request_fn = |req: Request| {
             let reader = payload.into_read();
-            let unit = Unit::new(
-                &req.agent,
-                &req.method,
-                &url,
-                req.headers,
-                &reader,
-                deadline,
-            );
-
-            unit::connect(unit, true, reader).map_err(|e| e.url(url.clone()))
+            let mut headers = req.headers;
+            // Bodies that can be resent are streamed as they are, rather than
+            // buffered in memory to be compressed.
+            #[cfg(feature = "gzip")]
+            let resendable = reader.factory.is_some() || reader.seekable.is_some();
+            #[cfg(feature = "gzip")]
+            let reader = match (req.agent.config.gzip_request_min_size, &reader.size) {
+                (Some(min_size), BodySize::Known(size))
+                    if *size > min_size
+                        && !resendable
+                        && !header::has_header(&headers, "content-encoding") =>
+                {
+                    headers.retain(|h| !h.is_name("content-length"));
+                    headers.push(Header::new("Content-Encoding", "gzip"));
+                    reader.gzip()?
+                }
+                _ => reader,
+            };
+            let mut reader = reader;
+            if req.content_md5
+                && !matches!(reader.size, BodySize::Empty)
+                && !header::has_header(&headers, "content-md5")
+            {
+                let digest = reader.content_md5(CONTENT_MD5_MAX_SIZE)?;
+                headers.push(Header::new("Content-MD5", &digest));
+            }
+            let overridden =
+                req.method_override && ["PUT", "PATCH", "DELETE"].contains(&&*req.method);
+            let method = if overridden {
+                let header = Header::new("X-HTTP-Method-Override", &req.method);
+                header::add_header(&mut headers, header);
+                "POST"
+            } else {
+                &req.method
+            };
+            let mut unit = Unit::new(&req.agent, method, &url, headers, &reader, deadline);
+            unit.digest_auth = req.digest_auth;
+            unit.simple_request = req.simple_request;
+            unit.rate_limit = req.rate_limit;
+            if req.allow_conflicting_length_encoding {
+                unit.add_conflicting_length(&reader);
+            }
+            unit.tap = req.tap;
+            #[cfg(feature = "charset")]
+            {
+                unit.force_charset = req.force_charset;
+            }
+
+            unit::connect(unit, true, reader)
         };
 
         let response = if !self.agent.state.middleware.is_empty() {
@@ -202,6 +557,36 @@ impl Request {
         self.do_call(Payload::Bytes(&json_bytes))
     }
 
+    /// Send items as newline delimited JSON, one line per item.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["json"] }`
+    ///
+    /// The items are serialized as they are sent, using the chunked `Transfer-Encoding`,
+    /// so they don't all need to be in memory. The `Content-Type` header is set to
+    /// `application/x-ndjson` unless already set.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// # ureq::is_test(true);
+    /// let items = (1..=3).map(|id| ureq::json!({ "id": id }));
+    /// let resp = ureq::post("http://httpbin.org/post")
+    ///     .send_ndjson(items)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn send_ndjson<T: serde::Serialize>(
+        mut self,
+        items: impl IntoIterator<Item = T>,
+    ) -> Result<Response> {
+        if self.header("Content-Type").is_none() {
+            self = self.set("Content-Type", "application/x-ndjson");
+        }
+
+        let reader = NdjsonReader::new(items.into_iter());
+        self.do_call(Payload::Reader(Box::new(reader)))
+    }
+
     /// Send data as bytes.
     ///
     /// The `Content-Length` header is implicitly set to the length of the serialized value.
@@ -218,6 +603,31 @@ impl Request {
         self.do_call(Payload::Bytes(data))
     }
 
+    /// Send data as bytes, and return only the status code of the response.
+    ///
+    /// The response body is read and discarded, so the connection can go back to the
+    /// pool. Unlike the other `send` methods, status codes >= 400 are returned as
+    /// `Ok` rather than [`Error::Status`], since there is no response left to inspect.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// # ureq::is_test(true);
+    /// let status = ureq::post("http://httpbin.org/post")
+    ///     .send_bytes_no_body(b"{\"event\": \"ping\"}")?;
+    /// assert_eq!(status, 200);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn send_bytes_no_body(self, data: &[u8]) -> Result<u16> {
+        let mut response = match self.do_call(Payload::Bytes(data)) {
+            Ok(response) => response,
+            Err(Error::Status(_, response)) => response,
+            Err(e) => return Err(e),
+        };
+        response.drain()?;
+        Ok(response.status())
+    }
+
     /// Send data as a string.
     ///
     /// The `Content-Length` header is implicitly set to the length of the serialized value.
@@ -274,6 +684,37 @@ impl Request {
         self.do_call(Payload::Bytes(&encoded.into_bytes()))
     }
 
+    /// Send the query parameters of the URL as a form-urlencoded body instead.
+    ///
+    /// Useful for endpoints that accept the same `a=1&b=2` pairs either way,
+    /// when the URL would grow too long. The query is removed from the URL, and
+    /// the `Content-Type` header is implicitly set to
+    /// application/x-www-form-urlencoded. Query parameters set on the agent
+    /// are still added to the URL.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// # ureq::is_test(true);
+    /// let resp = ureq::post("http://httpbin.org/post")
+    ///     .query("foo", "bar")
+    ///     .query("foo2", "bar2")
+    ///     .send_query_as_body()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn send_query_as_body(mut self) -> Result<Response> {
+        let mut encoded = String::new();
+        if let Ok(mut url) = self.parse_url() {
+            encoded = url.query().unwrap_or_default().to_string();
+            url.set_query(None);
+            self.url = url.to_string();
+        }
+        if self.header("Content-Type").is_none() {
+            self = self.set("Content-Type", "application/x-www-form-urlencoded");
+        }
+        self.do_call(Payload::Bytes(encoded.as_bytes()))
+    }
+
     /// Send data from a reader.
     ///
     /// If no Content-Length and Transfer-Encoding header has been set, it uses the [chunked transfer encoding](https://tools.ietf.org/html/rfc7230#section-4.1).
@@ -297,6 +738,76 @@ impl Request {
         self.do_call(Payload::Reader(Box::new(reader)))
     }
 
+    /// Send data from a reader, which is created by calling `factory`.
+    ///
+    /// This works like [`Request::send`], except that the body can be sent
+    /// again. If the request uses a pooled connection that turns out to be
+    /// closed by the server, the request is retried on a new connection
+    /// with a fresh reader from `factory`. As with other bodies, only
+    /// idempotent methods such as PUT are retried.
+    ///
+    /// ```no_run
+    /// use std::io::{Cursor, Read};
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// let data = vec![0x20_u8; 100];
+    /// let resp = ureq::put("http://httpbin.org/put")
+    ///     .send_with_body_factory(|| Box::new(Cursor::new(data.clone())) as Box<dyn Read>)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn send_with_body_factory(self, factory: impl Fn() -> Box<dyn Read>) -> Result<Response> {
+        self.do_call(Payload::Factory(&factory))
+    }
+
+    /// Send `len` bytes from a reader that can seek, starting at its beginning.
+    ///
+    /// This works like [`Request::send_with_body_factory`], except that the
+    /// reader is seeked back to the start before every attempt instead of
+    /// being created again. The `Content-Length` header is set to `len`.
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let file = File::open("upload.bin")?;
+    /// let len = file.metadata()?.len();
+    /// let resp = ureq::put("http://httpbin.org/put")
+    ///     .send_seekable(file, len)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn send_seekable(self, reader: impl Read + Seek, len: u64) -> Result<Response> {
+        let reader = RefCell::new(reader);
+        self.do_call(Payload::Seekable(&reader, len))
+    }
+
+    /// Send data from a reader, followed by its checksum in a trailer.
+    ///
+    /// The body is sent with `Transfer-Encoding: chunked`, replacing any
+    /// `Content-Length` header, and the checksum is computed while it's sent.
+    /// The request declares the trailer with a `Trailer` header, and sends it
+    /// after the last chunk, for example `x-checksum-sha256: <base64>`.
+    ///
+    /// ```no_run
+    /// use std::io::Cursor;
+    /// use ureq::Checksum;
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// let read = Cursor::new(vec![0x20; 100]);
+    /// let resp = ureq::put("http://httpbin.org/put")
+    ///     .send_with_body_checksum(read, Checksum::Sha256)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn send_with_body_checksum(
+        mut self,
+        reader: impl Read,
+        checksum: Checksum,
+    ) -> Result<Response> {
+        self.headers.retain(|h| !h.is_name("content-length"));
+        self.set("Transfer-Encoding", "chunked")
+            .set("Trailer", checksum.trailer_name())
+            .do_call(Payload::Checksummed(Box::new(reader), checksum))
+    }
+
     /// Set a header field.
     ///
     /// ```
@@ -314,6 +825,26 @@ impl Request {
         self
     }
 
+    /// Set the `Priority` header of [RFC 9218](https://www.rfc-editor.org/rfc/rfc9218),
+    /// for servers and proxies that schedule responses by priority.
+    ///
+    /// `urgency` goes from 0, the most urgent, to 7, and is clamped to that range.
+    /// The default for requests without the header is 3. `incremental` tells that
+    /// the response is useful before all of it has arrived.
+    ///
+    /// ```
+    /// let req = ureq::get("/my_page")
+    ///     .priority(5, true);
+    /// assert_eq!(req.header("priority"), Some("u=5, i"));
+    /// ```
+    pub fn priority(self, urgency: u8, incremental: bool) -> Self {
+        let mut value = format!("u={}", urgency.min(7));
+        if incremental {
+            value.push_str(", i");
+        }
+        self.set("Priority", &value)
+    }
+
     /// Returns the value for a set header.
     ///
     /// ```
@@ -498,6 +1029,143 @@ impl Request {
     pub fn request_url(&self) -> Result<RequestUrl> {
         Ok(RequestUrl::new(self.parse_url()?))
     }
+
+    /// The name and value of the cookies from the agent's jar that would be sent
+    /// with a request to `url`.
+    ///
+    /// This runs the same matching as sending the request, without any network call,
+    /// which is handy when debugging sessions.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// # ureq::is_test(true);
+    /// let req = ureq::get("http://example.com/");
+    /// let url = req.request_url()?.as_url().clone();
+    ///
+    /// // a new agent has no cookies
+    /// assert!(req.preview_cookies(&url).is_empty());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "cookies")]
+    pub fn preview_cookies(&self, url: &Url) -> Vec<(String, String)> {
+        unit::match_cookies(&self.agent, url)
+            .into_iter()
+            .map(|c| (c.name().to_string(), c.value().to_string()))
+            .collect()
+    }
+
+    /// The in-memory body this request is being sent with, as seen by middleware.
+    ///
+    /// `None` for bodyless requests and for bodies streamed from a reader.
+    #[cfg(test)]
+    pub(crate) fn body_preview(&self) -> Option<&[u8]> {
+        self.body_preview.as_deref()
+    }
+
+    /// Send the request on a new thread, returning a handle to poll for the response.
+    ///
+    /// This is a thin wrapper around [`Request::call()`] for callers that can't block,
+    /// not an async runtime: each call uses a thread of its own.
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// let mut handle = ureq::get("http://example.com/").call_nonblocking();
+    /// let resp = loop {
+    ///     if let Some(result) = handle.try_get() {
+    ///         break result?;
+    ///     }
+    ///     // do other work
+    /// };
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn call_nonblocking(self) -> CallHandle {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            // The handle may have been dropped, then nobody wants the result.
+            let _ = tx.send(self.call());
+        });
+        CallHandle { rx: Some(rx) }
+    }
+
+    /// Capture the method, URL and headers of this request, to send it again
+    /// later with [`Agent::replay()`].
+    ///
+    /// The body isn't known until the request is sent. Called from
+    /// [`Middleware`](crate::Middleware) of an agent with
+    /// [`AgentBuilder::record_request_bodies`](crate::AgentBuilder::record_request_bodies),
+    /// the recording has the body of requests sent with [`Request::send_bytes`] or
+    /// [`Request::send_string`] (before any charset encoding). Otherwise it has none,
+    /// and [`RequestRecording::body`] can be set to replay the request with an
+    /// in-memory body.
+    ///
+    /// ```
+    /// let req = ureq::post("http://example.com/form")
+    ///     .query("page", "2")
+    ///     .set("X-API-Key", "foobar");
+    /// let mut recording = req.to_recording();
+    /// recording.body = Some(b"name=martin".to_vec());
+    ///
+    /// assert_eq!(recording.url, "http://example.com/form?page=2");
+    /// ```
+    pub fn to_recording(&self) -> RequestRecording {
+        RequestRecording {
+            method: self.method.clone(),
+            url: self.url.clone(),
+            headers: self
+                .headers
+                .iter()
+                .map(|h| {
+                    let value = String::from_utf8_lossy(h.value_raw()).into_owned();
+                    (h.name().to_string(), value)
+                })
+                .collect(),
+            body: self.body_preview.clone(),
+        }
+    }
+}
+
+/// A request running on a thread of its own, made by [`Request::call_nonblocking()`].
+#[derive(Debug)]
+pub struct CallHandle {
+    rx: Option<mpsc::Receiver<Result<Response>>>,
+}
+
+impl CallHandle {
+    /// The result of the request, if it has completed. Doesn't block.
+    ///
+    /// Returns `None` while the request is in progress, and again on every call
+    /// after the result was returned.
+    pub fn try_get(&mut self) -> Option<Result<Response>> {
+        let result = match self.rx.as_ref()?.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => return None,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                Err(ErrorKind::Io.msg("request thread panicked"))
+            }
+        };
+        self.rx = None;
+        Some(result)
+    }
+}
+
+/// A request as plain data, made by [`Request::to_recording()`] and sent
+/// again with [`Agent::replay()`].
+///
+/// With the `serde` feature, recordings can be serialized with serde, for example
+/// to keep them as test fixtures.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RequestRecording {
+    /// The HTTP method, such as `POST`.
+    pub method: String,
+    /// The URL, including any query parameters.
+    pub url: String,
+    /// The header fields in the order they were set.
+    pub headers: Vec<(String, String)>,
+    /// The body to send, if any.
+    pub body: Option<Vec<u8>>,
 }
 
 /// Parsed result of a request url with handy inspection methods.
@@ -599,6 +1267,17 @@ mod tests {
             .ok();
     }
 
+    #[test]
+    fn priority_header() {
+        let req = crate::agent().get("https://example.com/").priority(5, true);
+        assert_eq!(req.header("priority"), Some("u=5, i"));
+
+        // A later call replaces the header, and urgency is clamped.
+        let req = req.priority(9, false);
+        assert_eq!(req.header("priority"), Some("u=7"));
+        assert_eq!(req.header_names(), vec!["priority"]);
+    }
+
     #[test]
     fn disallow_empty_host() {
         let req = crate::agent().get("file:///some/path");
@@ -611,4 +1290,68 @@ mod tests {
 
         assert_eq!(req.call().unwrap_err().kind(), crate::ErrorKind::InvalidUrl);
     }
+
+    #[test]
+    fn body_preview() {
+        use crate::middleware::MiddlewareNext;
+        use std::sync::{Arc, Mutex};
+
+        let seen = Arc::new(Mutex::new(vec![]));
+        let seen2 = seen.clone();
+        let agent = crate::builder()
+            .record_request_bodies(true)
+            .middleware(move |req: Request, next: MiddlewareNext| {
+                seen2
+                    .lock()
+                    .unwrap()
+                    .push(req.body_preview().map(|b| b.to_vec()));
+                next.handle(req)
+            })
+            .build();
+
+        for path in ["/preview1", "/preview2", "/preview3", "/preview4"] {
+            crate::test::set_handler(path, |_| {
+                crate::test::make_response(200, "OK", vec![], vec![])
+            });
+        }
+        agent
+            .post("test://host/preview1")
+            .send_string("hello")
+            .unwrap();
+        agent
+            .post("test://host/preview2")
+            .send_bytes(&[1, 2])
+            .unwrap();
+        agent
+            .post("test://host/preview3")
+            .send(&b"abc"[..])
+            .unwrap();
+        agent.get("test://host/preview4").call().unwrap();
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![Some(b"hello".to_vec()), Some(vec![1, 2]), None, None]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "cookies")]
+    fn preview_cookies() {
+        use cookie::Cookie;
+
+        let agent = Agent::new();
+        let url: Url = "https://example.com/".parse().unwrap();
+        let other: Url = "https://other.com/".parse().unwrap();
+        let matching: Cookie = "session=abc; Domain=example.com; Path=/".parse().unwrap();
+        let unrelated: Cookie = "theme=dark; Domain=other.com; Path=/".parse().unwrap();
+        let tin = &agent.state.cookie_tin;
+        tin.store_response_cookies(vec![matching].into_iter(), &url);
+        tin.store_response_cookies(vec![unrelated].into_iter(), &other);
+
+        let req = agent.get("https://example.com/");
+        assert_eq!(
+            req.preview_cookies(&url),
+            vec![("session".to_string(), "abc".to_string())]
+        );
+    }
 }
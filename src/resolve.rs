@@ -27,6 +27,30 @@ where
     }
 }
 
+/// Which IP versions to connect with.
+///
+/// Resolved addresses of other versions are skipped before connecting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum IpFamily {
+    /// Use both IPv4 and IPv6 addresses, in the order resolved. This is the default.
+    Dual,
+    /// Only use IPv4 addresses.
+    V4Only,
+    /// Only use IPv6 addresses.
+    V6Only,
+}
+
+impl IpFamily {
+    pub(crate) fn filter(self, addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+        match self {
+            IpFamily::Dual => addrs,
+            IpFamily::V4Only => addrs.into_iter().filter(|a| a.is_ipv4()).collect(),
+            IpFamily::V6Only => addrs.into_iter().filter(|a| a.is_ipv6()).collect(),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct ArcResolver(Arc<dyn Resolver>);
 
@@ -52,3 +76,17 @@ impl std::ops::Deref for ArcResolver {
         self.0.as_ref()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ip_family_filter() {
+        let v4: SocketAddr = "127.0.0.1:80".parse().unwrap();
+        let v6: SocketAddr = "[::1]:80".parse().unwrap();
+        assert_eq!(IpFamily::Dual.filter(vec![v6, v4]), vec![v6, v4]);
+        assert_eq!(IpFamily::V4Only.filter(vec![v6, v4]), vec![v4]);
+        assert_eq!(IpFamily::V6Only.filter(vec![v6, v4]), vec![v6]);
+    }
+}
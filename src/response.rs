@@ -1,18 +1,27 @@
+use std::collections::HashMap;
 use std::io::{self, Cursor, Read};
 use std::net::SocketAddr;
 use std::num::NonZeroUsize;
+use std::path::Path;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 use std::{fmt, io::BufRead};
 
 use log::debug;
 use url::Url;
 
+use crate::auth::{self, AuthChallenge};
 use crate::body::SizedReader;
 use crate::chunked::Decoder as ChunkDecoder;
 use crate::error::{Error, ErrorKind::BadStatus};
+use crate::hash::random_u64;
 use crate::header::{get_all_headers, get_header, Header, HeaderLine};
+use crate::image::{self, Dimensions};
 use crate::pool::{PoolReturnRead, PoolReturner};
-use crate::stream::{DeadlineStream, ReadOnlyStream, Stream};
+use crate::stream::{DeadlineStream, ReadOnlyStream, ReadWrite, Stream};
+use crate::throttle::Throttle;
 use crate::unit::Unit;
 use crate::{stream, Agent, ErrorKind};
 
@@ -31,10 +40,11 @@ use brotli_decompressor::Decompressor as BrotliDecoder;
 pub const DEFAULT_CONTENT_TYPE: &str = "text/plain";
 pub const DEFAULT_CHARACTER_SET: &str = "utf-8";
 const INTO_STRING_LIMIT: usize = 10 * 1_024 * 1_024;
-// Follow the example of curl and limit a single header to 100kB:
-// https://curl.se/libcurl/c/CURLOPT_HEADERFUNCTION.html
-const MAX_HEADER_SIZE: usize = 100 * 1_024;
+// Default limit for a single header line, configurable with
+// AgentBuilder::max_header_line_length.
+pub(crate) const DEFAULT_MAX_HEADER_LINE_LENGTH: usize = 100 * 1_024;
 const MAX_HEADER_COUNT: usize = 100;
+const MAX_INTERIM_RESPONSES: usize = 10;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 enum ConnectionOption {
@@ -82,25 +92,104 @@ pub struct Response {
     pub(crate) status: u16,
     pub(crate) headers: Vec<Header>,
     pub(crate) reader: Box<dyn Read + Send + Sync + 'static>,
+    /// The connection the body is yet to be read from, if it wasn't buffered already.
+    /// The `reader` is built from it on first use.
+    pub(crate) pending: Option<Box<PendingBody>>,
     /// The socket address of the server that sent the response.
     pub(crate) remote_addr: SocketAddr,
     /// The socket address of the client that sent the request.
     pub(crate) local_addr: SocketAddr,
+    /// The application protocol negotiated with ALPN, for a TLS connection.
+    pub(crate) alpn_protocol: Option<String>,
+    /// Whether the request was a HEAD, so the response has no body.
+    pub(crate) head_request: bool,
     /// The redirect history of this response, if any. The history starts with
     /// the first response received and ends with the response immediately
     /// previous to this one.
     ///
     /// If this response was not redirected, the history is empty.
     pub(crate) history: Vec<Url>,
+    /// The Content-Encoding header as sent by the server. Kept separately since
+    /// the header itself is removed when the body is decompressed.
+    pub(crate) content_encoding: Option<String>,
+    /// Bytes left of a length-delimited body, counted down by the body reader.
+    pub(crate) remaining: Option<Arc<AtomicU64>>,
+    /// Whether into_string() may look for a charset in the body.
+    #[cfg(feature = "charset")]
+    pub(crate) charset_sniffing: bool,
+    /// Charset that into_string() decodes with, whatever the response declares.
+    #[cfg(feature = "charset")]
+    pub(crate) force_charset: Option<String>,
 }
 
 /// index into status_line where we split: HTTP/1.1 200 OK
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub(crate) struct ResponseStatusIndex {
+    /// The end of the HTTP version.
     pub(crate) http_version: usize,
+    /// The end of the status code.
     pub(crate) response_code: usize,
 }
 
+/// The status and headers of a [`Response`], split from the body by
+/// [`Response::into_parts()`].
+#[derive(Debug, Clone)]
+pub struct ResponseHead {
+    url: Url,
+    status_line: String,
+    index: ResponseStatusIndex,
+    status: u16,
+    headers: Vec<Header>,
+}
+
+impl ResponseHead {
+    /// The URL we ended up at. This can differ from the request url when
+    /// we have followed redirects.
+    pub fn get_url(&self) -> &str {
+        &self.url[..]
+    }
+
+    /// The http version: `HTTP/1.1`
+    pub fn http_version(&self) -> &str {
+        &self.status_line.as_str()[0..self.index.http_version]
+    }
+
+    /// The status as a u16: `200`
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// The status text: `OK`
+    pub fn status_text(&self) -> &str {
+        self.status_line.as_str()[self.index.response_code..].trim()
+    }
+
+    /// The header value for the given name, or None if not found.
+    ///
+    /// See [`Response::header()`].
+    pub fn header(&self, name: &str) -> Option<&str> {
+        get_header(&self.headers, name)
+    }
+
+    /// A list of the header names, lowercased to be uniform.
+    pub fn headers_names(&self) -> Vec<String> {
+        self.headers
+            .iter()
+            .map(|h| h.name().to_lowercase())
+            .collect()
+    }
+
+    /// Tells if the head has the named header.
+    pub fn has(&self, name: &str) -> bool {
+        self.header(name).is_some()
+    }
+
+    /// All headers corresponding values for the give name, or empty vector.
+    pub fn all(&self, name: &str) -> Vec<&str> {
+        get_all_headers(&self.headers, name)
+    }
+}
+
 impl fmt::Debug for Response {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -155,7 +244,21 @@ impl Response {
     /// The HTTP spec allows for non-utf8 status texts. This uses from_utf8_lossy to
     /// convert such lines to &str.
     pub fn status_text(&self) -> &str {
-        self.status_line.as_str()[self.index.response_code + 1..].trim()
+        self.status_line.as_str()[self.index.response_code..].trim()
+    }
+
+    /// The status code as sent by the server, before parsing: `b"200"`
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// let resp: ureq::Response = "HTTP/1.1 999 Weird\r\n\r\n".parse()?;
+    /// assert_eq!(resp.raw_status_bytes(), b"999");
+    /// assert_eq!(resp.status(), 999);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn raw_status_bytes(&self) -> &[u8] {
+        &self.status_line.as_bytes()[self.index.response_code - 3..self.index.response_code]
     }
 
     /// The header value for the given name, or None if not found.
@@ -183,6 +286,46 @@ impl Response {
             .collect()
     }
 
+    /// Whether this response has no body, from its status, headers and the request method.
+    ///
+    /// True for `204 No Content`, `304 Not Modified`, responses to `HEAD`
+    /// and responses with `Content-Length: 0`. Reading the body of such a
+    /// response gives nothing, so there's no need to try.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// # ureq::is_test(true);
+    /// let resp = ureq::head("http://example.com/").call()?;
+    /// assert!(resp.is_empty_body());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn is_empty_body(&self) -> bool {
+        self.head_request
+            || matches!(self.status, 204 | 304)
+            || self.header("content-length").map(str::trim) == Some("0")
+    }
+
+    /// The number of header fields in this response, counting repeated names.
+    pub fn header_count(&self) -> usize {
+        self.headers.len()
+    }
+
+    /// The distinct header names in this response, in the order they first
+    /// appear and as the server wrote them.
+    ///
+    /// Names that differ only in case count as the same, like in
+    /// [`Response::header()`].
+    pub fn header_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = vec![];
+        for header in &self.headers {
+            if !names.iter().any(|n| header.is_name(n)) {
+                names.push(header.name());
+            }
+        }
+        names
+    }
+
     /// Tells if the response has the named header.
     pub fn has(&self, name: &str) -> bool {
         self.header(name).is_some()
@@ -193,6 +336,188 @@ impl Response {
         get_all_headers(&self.headers, name)
     }
 
+    /// All values of the named header joined with `, `, as if the server sent
+    /// them in one header field. `None` if there is no such header.
+    ///
+    /// `Set-Cookie` values can't be joined without changing their meaning, so
+    /// for that header this gives the first value, like [`Response::header()`].
+    /// Use [`Response::all()`] to get all of them.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// let resp: ureq::Response =
+    ///     "HTTP/1.1 200 OK\r\nVia: 1.1 a\r\nVia: 1.1 b\r\n\r\n".parse()?;
+    /// assert_eq!(resp.header_joined("via").as_deref(), Some("1.1 a, 1.1 b"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn header_joined(&self, name: &str) -> Option<String> {
+        if name.eq_ignore_ascii_case("set-cookie") {
+            return self.header(name).map(String::from);
+        }
+        let values = self.all(name);
+        if values.is_empty() {
+            return None;
+        }
+        Some(values.join(", "))
+    }
+
+    /// The challenges of all `WWW-Authenticate` headers in this response.
+    ///
+    /// A single header can contain multiple challenges, and a response can have
+    /// multiple headers. The challenges are returned in the order they appear.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// let s = "HTTP/1.1 401 Unauthorized\r\n\
+    ///     WWW-Authenticate: Basic realm=\"example\"\r\n\
+    ///     \r\n";
+    /// let resp: ureq::Response = s.parse()?;
+    /// let challenges = resp.auth_challenges();
+    /// assert!(challenges[0].is_scheme("basic"));
+    /// assert_eq!(challenges[0].param("realm"), Some("example"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn auth_challenges(&self) -> Vec<AuthChallenge> {
+        self.all("www-authenticate")
+            .into_iter()
+            .flat_map(auth::parse_challenges)
+            .collect()
+    }
+
+    /// The links of all `Link` headers in this response, keyed by relation type.
+    ///
+    /// Relative URLs are resolved against the URL of the response. If several
+    /// links share a relation type, the first one wins. This is handy to follow
+    /// `next` and `prev` links of paginated APIs.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// let s = "HTTP/1.1 200 OK\r\n\
+    ///     Link: </items?page=3>; rel=\"next\", </items?page=1>; rel=\"prev\"\r\n\
+    ///     \r\n";
+    /// let resp: ureq::Response = s.parse()?;
+    /// let links = resp.links();
+    /// assert_eq!(links["next"], "https://example.com/items?page=3");
+    /// assert_eq!(links["prev"], "https://example.com/items?page=1");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn links(&self) -> HashMap<String, String> {
+        let mut links = HashMap::new();
+        for (target, rels) in self.all("link").into_iter().flat_map(parse_links) {
+            let url = match self.url.join(target) {
+                Ok(url) => url.to_string(),
+                Err(_) => continue,
+            };
+            for rel in rels.split_ascii_whitespace() {
+                links
+                    .entry(rel.to_ascii_lowercase())
+                    .or_insert_with(|| url.clone());
+            }
+        }
+        links
+    }
+
+    /// The value of the "Content-Encoding" header, as sent by the server.
+    ///
+    /// When ureq decompresses the body, it removes the header itself from the
+    /// response, but this accessor still returns the original value.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// # ureq::is_test(true);
+    /// let resp = ureq::get("http://example.com/").call()?;
+    /// assert_eq!(resp.content_encoding(), None);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn content_encoding(&self) -> Option<&str> {
+        self.content_encoding.as_deref()
+    }
+
+    /// The "Content-Encoding" ureq decoded the body from, if it did.
+    ///
+    /// Unlike [`Response::content_encoding()`], this is `None` when the body is
+    /// passed on as sent, such as for encodings ureq doesn't support.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// let s = "HTTP/1.1 200 OK\r\n\
+    ///     Content-Encoding: identity\r\n\
+    ///     \r\n";
+    /// let resp: ureq::Response = s.parse()?;
+    /// assert_eq!(resp.was_decompressed(), None);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn was_decompressed(&self) -> Option<&str> {
+        // The header is removed when, and only when, the body is decompressed.
+        self.content_encoding()
+            .filter(|_| !self.has("content-encoding"))
+    }
+
+    /// The number of body bytes left to read, if the body is length-delimited.
+    ///
+    /// This is `None` for chunked bodies and bodies delimited by closing the
+    /// connection. For a compressed body the count is of the compressed bytes,
+    /// which the decompressor may read ahead of what the caller has consumed.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// let s = "HTTP/1.1 200 OK\r\n\
+    ///     Content-Length: 5\r\n\
+    ///     \r\n\
+    ///     hello";
+    /// let resp: ureq::Response = s.parse()?;
+    /// assert_eq!(resp.bytes_remaining(), Some(5));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn bytes_remaining(&self) -> Option<u64> {
+        self.remaining.as_ref().map(|r| r.load(Ordering::Relaxed))
+    }
+
+    /// The offset of the first body byte in the full resource, for a `206 Partial Content`
+    /// response with a single range in `Content-Range`.
+    ///
+    /// This is `None` for any other response, including a full `200 OK` and
+    /// `multipart/byteranges` responses (see [`Response::byteranges()`]).
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// let s = "HTTP/1.1 206 Partial Content\r\n\
+    ///     Content-Range: bytes 500-999/2000\r\n\
+    ///     Content-Length: 500\r\n\
+    ///     \r\n";
+    /// let resp: ureq::Response = s.parse()?;
+    /// assert_eq!(resp.range_start(), Some(500));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn range_start(&self) -> Option<u64> {
+        if self.status != 206 {
+            return None;
+        }
+        let (start, _) = parse_content_range(self.header("content-range")?)?;
+        Some(start)
+    }
+
+    /// The value of the "Transfer-Encoding" header, if any.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// # ureq::is_test(true);
+    /// let resp = ureq::get("http://example.com/").call()?;
+    /// assert_eq!(resp.transfer_encoding(), Some("chunked"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn transfer_encoding(&self) -> Option<&str> {
+        self.header("transfer-encoding")
+    }
+
     /// The content type part of the "Content-Type" header without
     /// the charset.
     ///
@@ -218,6 +543,26 @@ impl Response {
             .unwrap_or(DEFAULT_CONTENT_TYPE)
     }
 
+    /// The parameters of the "Content-Type", such as `charset` or `boundary`.
+    ///
+    /// Parameter names are lowercased, and quoted values are unquoted.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// let s = "HTTP/1.1 200 OK\r\n\
+    ///     Content-Type: multipart/mixed; boundary=\"abc\"\r\n\
+    ///     \r\n";
+    /// let resp: ureq::Response = s.parse()?;
+    /// assert_eq!(resp.content_type_params()["boundary"], "abc");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn content_type_params(&self) -> HashMap<String, String> {
+        self.header("content-type")
+            .map(parse_content_type_params)
+            .unwrap_or_default()
+    }
+
     /// The character set part of the "Content-Type".
     ///
     /// Example:
@@ -240,6 +585,14 @@ impl Response {
         self.remote_addr
     }
 
+    /// The application protocol agreed on with ALPN during the TLS handshake.
+    ///
+    /// `None` for plain HTTP, or when the server didn't pick one of the protocols
+    /// offered with [`AgentBuilder::alpn_protocols`](crate::AgentBuilder::alpn_protocols).
+    pub fn alpn_protocol(&self) -> Option<&str> {
+        self.alpn_protocol.as_deref()
+    }
+
     /// The local address the request was made from.
     pub fn local_addr(&self) -> SocketAddr {
         self.local_addr
@@ -281,144 +634,314 @@ impl Response {
     /// # Ok(())
     /// # }
     /// ```
-    pub fn into_reader(self) -> Box<dyn Read + Send + Sync + 'static> {
+    pub fn into_reader(mut self) -> Box<dyn Read + Send + Sync + 'static> {
+        self.body_reader();
         self.reader
     }
 
-    // Determine what to do with the connection after we've read the body.
-    fn connection_option(
-        response_version: &str,
-        connection_header: Option<&str>,
-    ) -> ConnectionOption {
-        // https://datatracker.ietf.org/doc/html/rfc9112#name-tear-down
-        // "A client that receives a "close" connection option MUST cease sending requests on that
-        // connection and close the connection after reading the response message containing the "close"
-        // connection option"
-        //
-        // Per https://www.rfc-editor.org/rfc/rfc2068#section-19.7.1, an HTTP/1.0 response can explicitly
-        // say "Connection: keep-alive" in response to a request with "Connection: keep-alive". We don't
-        // send "Connection: keep-alive" in the request but are willing to accept in the response anyhow.
-        use ConnectionOption::*;
-        let is_http10 = response_version.eq_ignore_ascii_case("HTTP/1.0");
-        match (is_http10, connection_header) {
-            (true, Some(c)) if c.eq_ignore_ascii_case("keep-alive") => KeepAlive,
-            (true, _) => Close,
-            (false, Some(c)) if c.eq_ignore_ascii_case("close") => Close,
-            (false, _) => KeepAlive,
-        }
+    /// Split the response into its head (status and headers) and the body reader.
+    ///
+    /// Unlike [`into_reader()`](#method.into_reader), this keeps the headers around
+    /// while the body is streamed.
+    ///
+    /// ```
+    /// use std::io::Read;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # ureq::is_test(true);
+    /// let resp = ureq::get("http://httpbin.org/bytes/100").call()?;
+    ///
+    /// let (head, mut reader) = resp.into_parts();
+    /// let mut bytes = vec![];
+    /// reader.read_to_end(&mut bytes)?;
+    ///
+    /// assert_eq!(head.header("Content-Length"), Some("100"));
+    /// assert_eq!(bytes.len(), 100);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn into_parts(mut self) -> (ResponseHead, Box<dyn Read + Send + Sync + 'static>) {
+        self.body_reader();
+        let head = ResponseHead {
+            url: self.url,
+            status_line: self.status_line,
+            index: self.index,
+            status: self.status,
+            headers: self.headers,
+        };
+        (head, self.reader)
     }
 
-    /// Determine how the body should be read, based on
-    /// <https://datatracker.ietf.org/doc/html/rfc9112#name-message-body-length>
-    fn body_type(
-        request_method: &str,
-        response_status: u16,
-        response_version: &str,
-        headers: &[Header],
-    ) -> BodyType {
-        let is_http10 = response_version.eq_ignore_ascii_case("HTTP/1.0");
-
-        let is_head = request_method.eq_ignore_ascii_case("head");
-        let has_no_body = is_head
-            || match response_status {
-                204 | 304 => true,
-                _ => false,
-            };
-
-        if has_no_body {
-            return BodyType::LengthDelimited(0);
-        }
-
-        let is_chunked = get_header(headers, "transfer-encoding")
-            .map(|enc| !enc.is_empty()) // whatever it says, do chunked
-            .unwrap_or(false);
-
-        // https://www.rfc-editor.org/rfc/rfc2068#page-161
-        // > a persistent connection with an HTTP/1.0 client cannot make
-        // > use of the chunked transfer-coding
-        let use_chunked = !is_http10 && is_chunked;
-
-        if use_chunked {
-            return BodyType::Chunked;
+    /// Take over the connection, for protocols other than HTTP such as after a
+    /// `101 Switching Protocols` response.
+    ///
+    /// Returns the bytes already read past the response head, followed by the
+    /// connection to read the rest from. No body framing or decompression is applied,
+    /// and the connection is no longer managed by the agent.
+    ///
+    /// Fails, giving the response back, if there is no connection left to take because
+    /// the body was empty or already read in full.
+    ///
+    /// ```no_run
+    /// use std::io::{Read, Write};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let resp = ureq::get("http://example.com/chat")
+    ///     .set("Connection", "Upgrade")
+    ///     .set("Upgrade", "chat")
+    ///     .call()?;
+    ///
+    /// if let Ok((buffered, mut conn)) = resp.into_raw() {
+    ///     conn.write_all(b"hello")?;
+    ///     let mut reply = buffered;
+    ///     conn.read_to_end(&mut reply)?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn into_raw(mut self) -> Result<(Vec<u8>, Box<dyn ReadWrite>), Response> {
+        match self.pending.take() {
+            Some(pending) => {
+                let stream: Stream = pending.stream.into();
+                Ok(stream.into_raw())
+            }
+            None => Err(self),
         }
+    }
 
-        let length = get_header(headers, "content-length").and_then(|v| v.parse::<usize>().ok());
+    /// Read and discard the rest of the body, returning the number of bytes discarded.
+    ///
+    /// Reading the body to the end allows the connection to be reused for later
+    /// requests. The count is of the (decompressed) body bytes, not the bytes on the wire.
+    ///
+    /// Example:
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// # ureq::is_test(true);
+    /// let mut resp = ureq::get("http://httpbin.org/bytes/100")
+    ///     .call()?;
+    ///
+    /// assert_eq!(resp.drain()?, 100);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn drain(&mut self) -> Result<u64, Error> {
+        Ok(io::copy(self.body_reader(), &mut io::sink())?)
+    }
 
-        match length {
-            Some(n) => BodyType::LengthDelimited(n),
-            None => BodyType::CloseDelimited,
-        }
+    /// Write the body to the file at `path`, returning the number of bytes written.
+    ///
+    /// The body is first written to a temporary file next to `path`, which is
+    /// renamed to `path` once the whole body has been read.
+    ///
+    /// A download that fails midway, for example because the connection closed
+    /// early, leaves no file at `path`, and an existing file there is kept as
+    /// it was. See [`Response::save_to_file_limited`] to also bound the size.
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// let resp = ureq::get("http://httpbin.org/bytes/100").call()?;
+    /// let path = std::path::Path::new("bytes.bin");
+    /// let written = resp.save_to_file(path)?;
+    /// assert_eq!(written, 100);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn save_to_file(self, path: &Path) -> Result<u64, Error> {
+        self.do_save_to_file(path, None)
     }
 
-    fn stream_to_reader(
-        mut stream: DeadlineStream,
-        unit: &Unit,
-        body_type: BodyType,
-        compression: Option<Compression>,
-        connection_option: ConnectionOption,
-    ) -> Box<dyn Read + Send + Sync + 'static> {
-        if connection_option == ConnectionOption::Close {
-            stream.inner_mut().set_unpoolable();
-        }
-        let inner = stream.inner_ref();
-        let result = inner.set_read_timeout(unit.agent.config.timeout_read);
-        if let Err(e) = result {
-            return Box::new(ErrorReader(e));
+    /// Like [`Response::save_to_file`], but fails for bodies larger than
+    /// `max_size` bytes, compressed or not, leaving no file at `path`.
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// let resp = ureq::get("http://httpbin.org/bytes/100").call()?;
+    /// let path = std::path::Path::new("bytes.bin");
+    /// let written = resp.save_to_file_limited(path, 10 * 1024 * 1024)?;
+    /// assert_eq!(written, 100);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn save_to_file_limited(self, path: &Path, max_size: u64) -> Result<u64, Error> {
+        self.do_save_to_file(path, Some(max_size))
+    }
+
+    fn do_save_to_file(self, path: &Path, max_size: Option<u64>) -> Result<u64, Error> {
+        use std::ffi::OsString;
+        use std::fs::{self, OpenOptions};
+
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+        let mut temp_name = OsString::from(".");
+        temp_name.push(file_name);
+        temp_name.push(format!(".{:016x}.part", random_u64()));
+        let temp_path = path.with_file_name(temp_name);
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
+        let limit = max_size.map_or(u64::MAX, |max| max.saturating_add(1));
+        let written = io::copy(&mut self.into_reader().take(limit), &mut file)
+            .and_then(|n| match max_size {
+                Some(max) if n > max => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("body is too large to save (over {} bytes)", max),
+                )),
+                _ => Ok(n),
+            })
+            .and_then(|n| file.sync_all().map(|_| n));
+        drop(file);
+        let written = written.and_then(|n| fs::rename(&temp_path, path).map(|_| n));
+        if written.is_err() {
+            fs::remove_file(&temp_path).ok();
         }
-        let buffer_len = inner.buffer().len();
-
-        let body_reader: Box<dyn Read + Send + Sync> = match body_type {
-            // Chunked responses have an unknown length, but do have an end of body
-            // marker. When we encounter the marker, we can return the underlying stream
-            // to the connection pool.
-            BodyType::Chunked => {
-                debug!("Chunked body in response");
-                Box::new(PoolReturnRead::new(ChunkDecoder::new(stream)))
+        written.map_err(|e| {
+            // Errors of ureq, such as a decompression bomb, are passed on as they are.
+            if matches!(e.get_ref(), Some(inner) if inner.is::<Error>()) {
+                *e.into_inner().unwrap().downcast::<Error>().unwrap()
+            } else {
+                e.into()
             }
-            // Responses with a content-length header means we should limit the reading
-            // of the body to the number of bytes in the header. Once done, we can
-            // return the underlying stream to the connection pool.
-            BodyType::LengthDelimited(len) => {
-                match NonZeroUsize::new(len) {
-                    None => {
-                        debug!("zero-length body returning stream directly to pool");
-                        let stream: Stream = stream.into();
-                        // TODO: This expect can actually panic if we get an error when
-                        // returning the stream to the pool. We reset the read timeouts
-                        // when we do that, and since that's a syscall it can fail.
-                        stream.return_to_pool().expect("returning stream to pool");
-                        Box::new(std::io::empty())
-                    }
-                    Some(len) => {
-                        let mut limited_read = LimitedRead::new(stream, len);
+        })
+    }
 
-                        if len.get() <= buffer_len {
-                            debug!("Body entirely buffered (length: {})", len);
-                            let mut buf = vec![0; len.get()];
-                            // TODO: This expect can actually panic if we get an error when
-                            // returning the stream to the pool. We reset the read timeouts
-                            // when we do that, and since that's a syscall it can fail.
-                            limited_read
-                                .read_exact(&mut buf)
-                                .expect("failed to read exact buffer length from stream");
-                            Box::new(Cursor::new(buf))
-                        } else {
-                            debug!("Streaming body until content-length: {}", len);
-                            Box::new(limited_read)
-                        }
-                    }
-                }
+    /// The width and height of an image body, read from the first bytes of the body.
+    ///
+    /// PNG, GIF, JPEG and WebP images are recognized. Only as much of the body as
+    /// needed is read, and the body can still be read in full afterwards. The bytes
+    /// read count towards [`Response::bytes_remaining`] only once they are read again.
+    /// Returns `None` if the body isn't one of those formats, or the dimensions aren't
+    /// found in the first 64 kB, and an error if reading the body fails.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// # ureq::is_test(true);
+    /// let mut resp = ureq::get("http://example.com/image.png").call()?;
+    /// if let Some((width, height)) = resp.image_dimensions()? {
+    ///     println!("{}x{}", width, height);
+    /// }
+    /// let body = resp.into_reader();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn image_dimensions(&mut self) -> io::Result<Option<(u32, u32)>> {
+        const MAX_PEEK: usize = 64 * 1024;
+
+        let before = self.bytes_remaining();
+        let mut head = Vec::new();
+        let mut buf = [0; 512];
+        let found = loop {
+            match image::dimensions(&head) {
+                Dimensions::Found(width, height) => break Ok(Some((width, height))),
+                Dimensions::Unknown => break Ok(None),
+                Dimensions::NeedMore if head.len() >= MAX_PEEK => break Ok(None),
+                Dimensions::NeedMore => {}
             }
-            BodyType::CloseDelimited => {
-                debug!("Body of unknown size - read until socket close");
-                Box::new(stream)
+            match self.body_reader().read(&mut buf) {
+                Ok(0) => break Ok(None),
+                Ok(n) => head.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => break Err(e),
             }
         };
 
-        match compression {
-            None => body_reader,
-            Some(c) => c.wrap_reader(body_reader),
+        // Put the bytes read back in front of the rest of the body, to be counted
+        // when they are read again.
+        let rest = std::mem::replace(self.body_reader(), Box::new(io::empty()));
+        let head: Box<dyn Read + Send + Sync> = match (&self.remaining, before) {
+            (Some(remaining), Some(before)) => {
+                let after = remaining.swap(before, Ordering::Relaxed);
+                Box::new(PeekedRead {
+                    head: Cursor::new(head),
+                    remaining: remaining.clone(),
+                    after,
+                })
+            }
+            _ => Box::new(Cursor::new(head)),
+        };
+        self.set_reader(Box::new(head.chain(rest)));
+        found
+    }
+
+    // Determine what to do with the connection after we've read the body.
+    fn connection_option(
+        response_version: &str,
+        connection_header: Option<&str>,
+    ) -> ConnectionOption {
+        // https://datatracker.ietf.org/doc/html/rfc9112#name-tear-down
+        // "A client that receives a "close" connection option MUST cease sending requests on that
+        // connection and close the connection after reading the response message containing the "close"
+        // connection option"
+        //
+        // Per https://www.rfc-editor.org/rfc/rfc2068#section-19.7.1, an HTTP/1.0 response can explicitly
+        // say "Connection: keep-alive" in response to a request with "Connection: keep-alive". We don't
+        // send "Connection: keep-alive" in the request but are willing to accept in the response anyhow.
+        use ConnectionOption::*;
+        let is_http10 = response_version.eq_ignore_ascii_case("HTTP/1.0");
+        match (is_http10, connection_header) {
+            (true, Some(c)) if c.eq_ignore_ascii_case("keep-alive") => KeepAlive,
+            (true, _) => Close,
+            (false, Some(c)) if c.eq_ignore_ascii_case("close") => Close,
+            (false, _) => KeepAlive,
+        }
+    }
+
+    /// Determine how the body should be read, based on
+    /// <https://datatracker.ietf.org/doc/html/rfc9112#name-message-body-length>
+    fn body_type(
+        request_method: &str,
+        response_status: u16,
+        response_version: &str,
+        headers: &[Header],
+    ) -> BodyType {
+        let is_http10 = response_version.eq_ignore_ascii_case("HTTP/1.0");
+
+        let is_head = request_method.eq_ignore_ascii_case("head");
+        let has_no_body = is_head
+            || match response_status {
+                204 | 304 => true,
+                _ => false,
+            };
+
+        if has_no_body {
+            return BodyType::LengthDelimited(0);
+        }
+
+        let is_chunked = get_header(headers, "transfer-encoding")
+            .map(|enc| !enc.is_empty()) // whatever it says, do chunked
+            .unwrap_or(false);
+
+        // https://www.rfc-editor.org/rfc/rfc2068#page-161
+        // > a persistent connection with an HTTP/1.0 client cannot make
+        // > use of the chunked transfer-coding
+        let use_chunked = !is_http10 && is_chunked;
+
+        if use_chunked {
+            return BodyType::Chunked;
+        }
+
+        let length = get_header(headers, "content-length").and_then(|v| v.parse::<usize>().ok());
+
+        match length {
+            Some(n) => BodyType::LengthDelimited(n),
+            None => BodyType::CloseDelimited,
+        }
+    }
+
+    /// Build the body reader, unless it's built already.
+    fn body_reader(&mut self) -> &mut Box<dyn Read + Send + Sync + 'static> {
+        if let Some(pending) = self.pending.take() {
+            self.reader = pending.into_reader();
         }
+        &mut self.reader
+    }
+
+    /// Replace the body reader, along with the connection it would be built from.
+    pub(crate) fn set_reader(&mut self, reader: Box<dyn Read + Send + Sync + 'static>) {
+        self.pending = None;
+        self.reader = reader;
     }
 
     /// Turn this response into a String of the response body. By default uses `utf-8`,
@@ -453,11 +976,28 @@ impl Response {
     ///
     /// I.e. `Content-Type: text/plain; charset=iso-8859-1` would be decoded in latin-1.
     ///
+    /// With [`AgentBuilder::charset_sniffing`](crate::AgentBuilder::charset_sniffing) enabled,
+    /// a Content-Type without charset makes into_string() look for a `<meta charset>`
+    /// declaration in the first 1024 bytes of the body.
+    ///
+    /// A charset set with [`Request::force_charset`](crate::Request::force_charset)
+    /// is used instead of both.
+    ///
     pub fn into_string(self) -> io::Result<String> {
         #[cfg(feature = "charset")]
-        let encoding = Encoding::for_label(self.charset().as_bytes())
-            .or_else(|| Encoding::for_label(DEFAULT_CHARACTER_SET.as_bytes()))
-            .unwrap();
+        let forced = self
+            .force_charset
+            .as_ref()
+            .and_then(|label| Encoding::for_label(label.as_bytes()));
+        #[cfg(feature = "charset")]
+        let (declared, sniff) = {
+            let content_type = self.header("content-type").unwrap_or_default();
+            let declared = content_type.to_ascii_lowercase().contains("charset=");
+            (
+                Encoding::for_label(self.charset().as_bytes()),
+                self.charset_sniffing && !declared && forced.is_none(),
+            )
+        };
 
         let mut buf: Vec<u8> = vec![];
         self.into_reader()
@@ -472,6 +1012,17 @@ impl Response {
 
         #[cfg(feature = "charset")]
         {
+            let sniffed = if sniff {
+                sniff_meta_charset(&buf[..buf.len().min(1024)])
+                    .and_then(|label| Encoding::for_label(label.as_bytes()))
+            } else {
+                None
+            };
+            let encoding = forced
+                .or(sniffed)
+                .or(declared)
+                .or_else(|| Encoding::for_label(DEFAULT_CHARACTER_SET.as_bytes()))
+                .unwrap();
             let (text, _, _) = encoding.decode(&buf);
             Ok(text.into_owned())
         }
@@ -481,6 +1032,87 @@ impl Response {
         }
     }
 
+    /// Iterate over the chunks of a `Transfer-Encoding: chunked` body, one item per chunk.
+    ///
+    /// The chunks are yielded as they arrive on the wire, so a `Content-Encoding`
+    /// such as gzip is not decoded. The terminating zero-length chunk ends the
+    /// iteration and returns the connection to the pool. A body that isn't chunked
+    /// is yielded whole, as a single item.
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// # ureq::is_test(true);
+    /// let resp = ureq::get("http://example.com/").call()?;
+    ///
+    /// for chunk in resp.into_chunks() {
+    ///     println!("{} bytes", chunk?.len());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn into_chunks(mut self) -> impl Iterator<Item = Result<Vec<u8>, Error>> {
+        match self.pending.take().map(|p| p.into_chunk_decoder()) {
+            Some(Ok(Ok(decoder))) => Chunks::Chunked(Box::new(decoder)),
+            Some(Ok(Err(e))) => Chunks::Failed(e),
+            Some(Err(pending)) => {
+                self.pending = Some(pending);
+                Chunks::Whole(self.into_reader())
+            }
+            None => Chunks::Whole(self.into_reader()),
+        }
+    }
+
+    /// Read the parts of a `206 Partial Content` response as `(start, end, bytes)`,
+    /// where `start` and `end` are the inclusive offsets given by `Content-Range`.
+    ///
+    /// A response to a request for several ranges has the type `multipart/byteranges`,
+    /// with a `Content-Range` header in each part. A response with a single range has
+    /// the `Content-Range` header on the response itself, and yields one part.
+    ///
+    /// Like [`Response::into_string`], this reads the whole body and fails if it is
+    /// larger than 10 megabytes, or if the parts are malformed.
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// let resp = ureq::get("http://httpbin.org/range/26")
+    ///     .set("Range", "bytes=0-4,10-14")
+    ///     .call()?;
+    /// for (start, end, bytes) in resp.byteranges()? {
+    ///     println!("{}-{}: {} bytes", start, end, bytes.len());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn byteranges(self) -> io::Result<impl Iterator<Item = (u64, u64, Vec<u8>)>> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        let single_range = self.header("content-range").map(parse_content_range);
+        let boundary = if self
+            .content_type()
+            .eq_ignore_ascii_case("multipart/byteranges")
+        {
+            let content_type = self.header("content-type").unwrap_or_default();
+            Some(content_type_boundary(content_type).ok_or_else(|| invalid("missing boundary"))?)
+        } else {
+            None
+        };
+
+        let mut buf: Vec<u8> = vec![];
+        self.into_reader()
+            .take((INTO_STRING_LIMIT + 1) as u64)
+            .read_to_end(&mut buf)?;
+        if buf.len() > INTO_STRING_LIMIT {
+            return Err(invalid("response too big for byteranges"));
+        }
+
+        let parts = match (boundary, single_range) {
+            (Some(boundary), _) => parse_byteranges(&buf, &boundary).map_err(invalid)?,
+            (None, Some(Some((start, end)))) => vec![(start, end, buf)],
+            (None, _) => return Err(invalid("no valid Content-Range in response")),
+        };
+        Ok(parts.into_iter())
+    }
+
     /// Read the body of this response into a serde_json::Value, or any other type that
     /// implements the [serde::Deserialize] trait.
     ///
@@ -532,88 +1164,229 @@ impl Response {
     /// ```
     #[cfg(feature = "json")]
     pub fn into_json<T: DeserializeOwned>(self) -> io::Result<T> {
-        use crate::stream::io_err_timeout;
-
         let reader = self.into_reader();
-        serde_json::from_reader(reader).map_err(|e| {
-            // This is to unify TimedOut io::Error in the API.
-            if let Some(kind) = e.io_error_kind() {
-                if kind == io::ErrorKind::TimedOut {
-                    return io_err_timeout(e.to_string());
-                }
-            }
-
-            io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("Failed to read JSON: {}", e),
-            )
-        })
+        serde_json::from_reader(reader).map_err(json_error)
     }
 
-    /// Create a response from a Read trait impl.
-    ///
-    /// This is hopefully useful for unit tests.
+    /// Read the elements of a top-level JSON array one at a time, without
+    /// holding the whole array in memory.
     ///
-    /// Example:
+    /// Each element is deserialized straight from the body, so an element
+    /// that doesn't deserialize into `T` is an error item that ends the
+    /// iteration, as do errors reading the body, or a body that isn't an array.
     ///
-    /// use std::io::Cursor;
+    /// Requires feature `ureq = { version = "*", features = ["json"] }`
     ///
-    /// let text = "HTTP/1.1 401 Authorization Required\r\n\r\nPlease log in\n";
-    /// let read = Cursor::new(text.to_string().into_bytes());
-    /// let resp = ureq::Response::do_from_read(read);
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// let resp: ureq::Response = "HTTP/1.1 200 OK\r\n\r\n[1, 2, 3]".parse()?;
     ///
-    /// assert_eq!(resp.status(), 401);
-    pub(crate) fn do_from_stream(stream: Stream, unit: Unit) -> Result<Response, Error> {
-        let remote_addr = stream.remote_addr;
-
-        let local_addr = match stream.socket() {
-            Some(sock) => sock.local_addr().map_err(Error::from)?,
-            None => std::net::SocketAddrV4::new(std::net::Ipv4Addr::new(127, 0, 0, 1), 0).into(),
-        };
-
-        //
-        // HTTP/1.1 200 OK\r\n
-        let mut stream = stream::DeadlineStream::new(stream, unit.deadline);
-
-        // The status line we can ignore non-utf8 chars and parse as_str_lossy().
-        let status_line = read_next_line(&mut stream, "the status line")?.into_string_lossy();
-        let (index, status) = parse_status_line(status_line.as_str())?;
-        let http_version = &status_line.as_str()[0..index.http_version];
-
-        let mut headers: Vec<Header> = Vec::new();
-        while headers.len() <= MAX_HEADER_COUNT {
-            let line = read_next_line(&mut stream, "a header")?;
-            if line.is_empty() {
-                break;
-            }
-            if let Ok(header) = line.into_header() {
-                headers.push(header);
-            }
+    /// let mut sum = 0;
+    /// for n in resp.into_json_array::<u32>() {
+    ///     sum += n?;
+    /// }
+    /// assert_eq!(sum, 6);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn into_json_array<T: DeserializeOwned>(self) -> impl Iterator<Item = Result<T, Error>> {
+        JsonArray {
+            reader: io::BufReader::new(self.into_reader()),
+            lookahead: None,
+            state: JsonArrayState::Start,
+            element: std::marker::PhantomData,
         }
+    }
 
-        if headers.len() > MAX_HEADER_COUNT {
-            return Err(ErrorKind::BadHeader.msg(
-                format!("more than {} header fields in response", MAX_HEADER_COUNT).as_str(),
-            ));
+    /// Read the body as an untyped `serde_json::Value`.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["json"] }`
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// # ureq::is_test(true);
+    /// let json = ureq::get("http://example.com/hello_world.json")
+    ///     .call()?
+    ///     .json_value()?;
+    ///
+    /// assert_eq!(json["hello"], "world");
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn json_value(self) -> io::Result<serde_json::Value> {
+        self.into_json()
+    }
+
+    /// Read the body as JSON and take out the value at a
+    /// [JSON Pointer](https://www.rfc-editor.org/rfc/rfc6901), such as `/data/0/id`.
+    ///
+    /// Returns `None` if there is no value at the pointer.
+    ///
+    /// Requires feature `ureq = { version = "*", features = ["json"] }`
+    ///
+    /// ```
+    /// # fn main() -> Result<(), ureq::Error> {
+    /// # ureq::is_test(true);
+    /// let hello = ureq::get("http://example.com/hello_world.json")
+    ///     .call()?
+    ///     .json_pointer("/hello")?;
+    ///
+    /// assert_eq!(hello, Some(ureq::json!("world")));
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn json_pointer(self, ptr: &str) -> io::Result<Option<serde_json::Value>> {
+        let mut value = self.json_value()?;
+        Ok(value.pointer_mut(ptr).map(serde_json::Value::take))
+    }
+
+    /// Create a response from a Read trait impl.
+    ///
+    /// This is hopefully useful for unit tests.
+    ///
+    /// Example:
+    ///
+    /// use std::io::Cursor;
+    ///
+    /// let text = "HTTP/1.1 401 Authorization Required\r\n\r\nPlease log in\n";
+    /// let read = Cursor::new(text.to_string().into_bytes());
+    /// let resp = ureq::Response::do_from_read(read);
+    ///
+    /// assert_eq!(resp.status(), 401);
+    pub(crate) fn do_from_stream(stream: Stream, unit: Unit) -> Result<Response, Error> {
+        let remote_addr = stream.remote_addr;
+
+        let local_addr = match stream.socket() {
+            Some(sock) => sock.local_addr().map_err(Error::from)?,
+            None => std::net::SocketAddrV4::new(std::net::Ipv4Addr::new(127, 0, 0, 1), 0).into(),
+        };
+        let alpn_protocol = stream
+            .inner_ref()
+            .alpn_protocol()
+            .map(|p| String::from_utf8_lossy(p).into_owned());
+
+        //
+        // HTTP/1.1 200 OK\r\n
+        let mut stream = stream::DeadlineStream::new(stream, unit.deadline);
+
+        if unit.simple_request {
+            return Self::do_from_simple_stream(
+                stream,
+                unit,
+                remote_addr,
+                local_addr,
+                alpn_protocol,
+            );
         }
 
-        let compression =
-            get_header(&headers, "content-encoding").and_then(Compression::from_header_value);
+        let max_line = unit.agent.config.max_header_line_length;
+        let mut interim = 0;
+        let (status_line, index, status, mut headers) = loop {
+            // The status line we can ignore non-utf8 chars and parse as_str_lossy().
+            let status_line =
+                read_next_line(&mut stream, "the status line", max_line)?.into_string_lossy();
+            let (index, status) = parse_status_line(status_line.as_str())?;
+
+            let mut headers: Vec<Header> = Vec::new();
+            while headers.len() <= MAX_HEADER_COUNT {
+                let line = read_next_line(&mut stream, "a header", max_line)?;
+                if line.is_empty() {
+                    break;
+                }
+                if let Ok(header) = line.into_header() {
+                    headers.push(header);
+                }
+            }
 
-        let connection_option =
+            if headers.len() > MAX_HEADER_COUNT {
+                return Err(ErrorKind::BadHeader.msg(
+                    format!("more than {} header fields in response", MAX_HEADER_COUNT).as_str(),
+                ));
+            }
+
+            // Interim responses, such as 100 Continue, come before the final response.
+            // 101 Switching Protocols is final, since the connection stops being HTTP.
+            if (100..200).contains(&status) && status != 101 {
+                interim += 1;
+                if interim > MAX_INTERIM_RESPONSES {
+                    return Err(BadStatus.msg(format!(
+                        "more than {} interim responses",
+                        MAX_INTERIM_RESPONSES
+                    )));
+                }
+                debug!("skipping interim response {}", status);
+                continue;
+            }
+            break (status_line, index, status, headers);
+        };
+        let http_version = &status_line.as_str()[0..index.http_version];
+
+        let content_encoding = get_header(&headers, "content-encoding").map(String::from);
+        let compression = content_encoding
+            .as_deref()
+            .and_then(Compression::from_header_value);
+
+        let mut connection_option =
             Self::connection_option(http_version, get_header(&headers, "connection"));
 
+        // Respect the limits the server announces for reusing the connection.
+        let responses = stream.inner_mut().count_response();
+        if connection_option == ConnectionOption::KeepAlive {
+            if let Some(value) = get_header(&headers, "keep-alive") {
+                let (timeout, max) = parse_keep_alive(value);
+                stream.inner_mut().set_keep_alive_timeout(timeout);
+                if matches!(max, Some(max) if responses >= max) {
+                    debug!("keep-alive max of {} responses reached", responses);
+                    connection_option = ConnectionOption::Close;
+                }
+            }
+        }
+
         let body_type = Self::body_type(&unit.method, status, http_version, &headers);
 
+        let required_trailers = if unit.agent.config.strict_trailers {
+            get_all_headers(&headers, "trailer")
+                .into_iter()
+                .flat_map(|v| v.split(','))
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .collect()
+        } else {
+            vec![]
+        };
+
         // remove Content-Encoding and length due to automatic decompression
         if compression.is_some() {
             headers.retain(|h| !h.is_name("content-encoding") && !h.is_name("content-length"));
         }
 
-        let reader =
-            Self::stream_to_reader(stream, &unit, body_type, compression, connection_option);
-
         let url = unit.url.clone();
+        let head_request = unit.method.eq_ignore_ascii_case("head");
+        #[cfg(feature = "charset")]
+        let charset_sniffing = unit.agent.config.charset_sniffing;
+        #[cfg(feature = "charset")]
+        let force_charset = unit.force_charset.clone();
+
+        let pending = PendingBody::new(
+            stream,
+            unit,
+            body_type,
+            compression,
+            connection_option,
+            required_trailers,
+        );
+        let remaining = pending.remaining.clone();
+        // A body that's already buffered is read right away, so the connection
+        // can go back to the pool.
+        let (reader, pending) = if pending.is_buffered() {
+            (pending.into_reader(), None)
+        } else {
+            let empty: Box<dyn Read + Send + Sync> = Box::new(io::empty());
+            (empty, Some(Box::new(pending)))
+        };
 
         let response = Response {
             url,
@@ -622,13 +1395,70 @@ impl Response {
             status,
             headers,
             reader,
+            pending,
             remote_addr,
             local_addr,
+            alpn_protocol,
+            head_request,
             history: vec![],
+            content_encoding,
+            remaining,
+            #[cfg(feature = "charset")]
+            charset_sniffing,
+            #[cfg(feature = "charset")]
+            force_charset,
         };
         Ok(response)
     }
 
+    /// An HTTP/0.9 response is only a body, ended by the server closing the connection.
+    fn do_from_simple_stream(
+        stream: stream::DeadlineStream,
+        unit: Unit,
+        remote_addr: SocketAddr,
+        local_addr: SocketAddr,
+        alpn_protocol: Option<String>,
+    ) -> Result<Response, Error> {
+        let status_line = "HTTP/0.9 200 OK".to_string();
+        let (index, status) = parse_status_line(&status_line)?;
+
+        let url = unit.url.clone();
+        let head_request = unit.method.eq_ignore_ascii_case("head");
+        #[cfg(feature = "charset")]
+        let charset_sniffing = unit.agent.config.charset_sniffing;
+        #[cfg(feature = "charset")]
+        let force_charset = unit.force_charset.clone();
+        let pending = PendingBody::new(
+            stream,
+            unit,
+            BodyType::CloseDelimited,
+            None,
+            ConnectionOption::Close,
+            vec![],
+        );
+
+        Ok(Response {
+            url,
+            status_line,
+            index,
+            status,
+            headers: vec![],
+            reader: Box::new(io::empty()),
+            pending: Some(Box::new(pending)),
+            remote_addr,
+            local_addr,
+            alpn_protocol,
+            head_request,
+            history: vec![],
+            content_encoding: None,
+            remaining: None,
+            #[cfg(feature = "charset")]
+            charset_sniffing,
+            #[cfg(feature = "charset")]
+            force_charset,
+        })
+    }
+
     #[cfg(test)]
     pub fn set_url(&mut self, url: Url) {
         self.url = url;
@@ -685,20 +1515,22 @@ fn parse_status_line(line: &str) -> Result<(ResponseStatusIndex, u16), Error> {
     }
     // https://tools.ietf.org/html/rfc7230#section-3.1.2
     //      status-line = HTTP-version SP status-code SP reason-phrase CRLF
-    let mut split: Vec<&str> = line.splitn(3, ' ').collect();
-    if split.len() == 2 {
-        // As a special case, we are lenient parsing lines without a space after the code.
-        // This is technically against spec. "HTTP/1.1 200\r\n"
-        split.push("");
-    }
-    if split.len() != 3 {
-        return Err(BadStatus.msg("Wrong number of tokens in status line"));
-    }
+    // We are lenient with the whitespace, allowing several spaces between the
+    // parts. As a special case, we also accept lines without a space after the
+    // code. This is technically against spec. "HTTP/1.1 200\r\n"
+    let version_end = line
+        .find(' ')
+        .ok_or_else(|| BadStatus.msg("Wrong number of tokens in status line"))?;
+    let code_start = version_end + count_spaces(&line[version_end..]);
+    let code_end = line[code_start..]
+        .find(' ')
+        .map(|i| code_start + i)
+        .unwrap_or(line.len());
 
     // https://tools.ietf.org/html/rfc7230#appendix-B
     //    HTTP-name = %x48.54.54.50 ; HTTP
     //    HTTP-version = HTTP-name "/" DIGIT "." DIGIT
-    let http_version = split[0];
+    let http_version = &line[..version_end];
     if !http_version.starts_with("HTTP/") {
         return Err(BadStatus.msg("HTTP version did not start with HTTP/"));
     }
@@ -710,11 +1542,15 @@ fn parse_status_line(line: &str) -> Result<(ResponseStatusIndex, u16), Error> {
         return Err(BadStatus.msg("HTTP version did not match format"));
     }
 
-    let status_str: &str = split[1];
+    let status_str = &line[code_start..code_end];
     //      status-code    = 3DIGIT
+    // Any three digits are accepted, also codes not defined by the spec, such as 999.
     if status_str.len() != 3 {
         return Err(BadStatus.msg("Status code was wrong length"));
     }
+    if !status_str.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(BadStatus.msg(format!("Status code was not digits ({})", status_str)));
+    }
 
     let status: u16 = status_str
         .parse()
@@ -723,12 +1559,167 @@ fn parse_status_line(line: &str) -> Result<(ResponseStatusIndex, u16), Error> {
     Ok((
         ResponseStatusIndex {
             http_version: http_version.len(),
-            response_code: http_version.len() + status_str.len(),
+            response_code: code_end,
         },
         status,
     ))
 }
 
+fn count_spaces(s: &str) -> usize {
+    s.len() - s.trim_start_matches(' ').len()
+}
+
+/// Parse a `Content-Range` value like `bytes 500-999/8000` into the inclusive offsets.
+fn parse_content_range(value: &str) -> Option<(u64, u64)> {
+    let value = value.trim();
+    let unit_end = value.find(' ')?;
+    if !value[..unit_end].eq_ignore_ascii_case("bytes") {
+        return None;
+    }
+    let range = value[unit_end..].trim_start().split('/').next()?;
+    let (start, end) = range.split_once('-')?;
+    let (start, end) = (start.trim().parse().ok()?, end.trim().parse().ok()?);
+    if start > end {
+        return None;
+    }
+    Some((start, end))
+}
+
+/// The `boundary` parameter of a multipart content type.
+fn content_type_boundary(content_type: &str) -> Option<String> {
+    parse_content_type_params(content_type)
+        .remove("boundary")
+        .filter(|v| !v.is_empty())
+}
+
+/// The parameters following the media type in a `Content-Type` value. Names are
+/// lowercased, and quoted values are unquoted. The first of repeated names wins.
+fn parse_content_type_params(content_type: &str) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    let mut rest = match content_type.split_once(';') {
+        Some((_, rest)) => rest,
+        None => return params,
+    };
+    while let Some((name, after)) = rest.split_once('=') {
+        // Skip anything without a value, such as "text/plain; flowed; a=b".
+        let name = name.rsplit(';').next().unwrap_or(name).trim();
+        let after = after.trim_start();
+        let (value, next) = if let Some(quoted) = after.strip_prefix('"') {
+            let mut value = String::new();
+            let mut end = quoted.len();
+            let mut chars = quoted.char_indices();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '\\' => value.extend(chars.next().map(|(_, c)| c)),
+                    '"' => {
+                        end = i + 1;
+                        break;
+                    }
+                    _ => value.push(c),
+                }
+            }
+            let next = quoted[end..].split_once(';').map(|(_, next)| next);
+            (value, next)
+        } else {
+            match after.split_once(';') {
+                Some((value, next)) => (value.trim().to_string(), Some(next)),
+                None => (after.trim().to_string(), None),
+            }
+        };
+        if !name.is_empty() {
+            params.entry(name.to_ascii_lowercase()).or_insert(value);
+        }
+        match next {
+            Some(next) => rest = next,
+            None => break,
+        }
+    }
+    params
+}
+
+/// A part of a `multipart/byteranges` body: first and last offset, and the bytes.
+type ByteRange = (u64, u64, Vec<u8>);
+
+/// Split a `multipart/byteranges` body into its parts. The length of each part is
+/// taken from its `Content-Range`, so parts may contain anything, even the boundary.
+fn parse_byteranges(body: &[u8], boundary: &str) -> Result<Vec<ByteRange>, &'static str> {
+    let delimiter = format!("--{}", boundary);
+    let delimiter = delimiter.as_bytes();
+    let mut pos = body
+        .windows(delimiter.len())
+        .position(|w| w == delimiter)
+        .ok_or("missing multipart boundary")?;
+
+    let mut parts = vec![];
+    loop {
+        pos += delimiter.len();
+        if body[pos..].starts_with(b"--") {
+            return Ok(parts);
+        }
+        pos += skip_line(&body[pos..]).ok_or("truncated multipart body")?;
+
+        let mut range = None;
+        loop {
+            let len = skip_line(&body[pos..]).ok_or("truncated multipart headers")?;
+            let line = String::from_utf8_lossy(&body[pos..pos + len]);
+            pos += len;
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("content-range") {
+                    range = parse_content_range(value);
+                }
+            }
+        }
+
+        let (start, end) = range.ok_or("multipart part without valid Content-Range")?;
+        if end - start >= (body.len() - pos) as u64 {
+            return Err("truncated multipart part");
+        }
+        let len = (end - start + 1) as usize;
+        let bytes = &body[pos..pos + len];
+        parts.push((start, end, bytes.to_vec()));
+        pos += len;
+
+        // The part is followed by a line break and the next delimiter.
+        let rest = &body[pos..];
+        let rest = rest
+            .strip_prefix(b"\r\n")
+            .or_else(|| rest.strip_prefix(b"\n"))
+            .unwrap_or(rest);
+        if !rest.starts_with(delimiter) {
+            return Err("missing multipart boundary after part");
+        }
+        pos = body.len() - rest.len();
+    }
+}
+
+/// The length of the line at the start of `buf`, including the line break.
+fn skip_line(buf: &[u8]) -> Option<usize> {
+    buf.iter().position(|b| *b == b'\n').map(|i| i + 1)
+}
+
+/// Parse the `timeout` (in seconds) and `max` parameters of a `Keep-Alive` header,
+/// such as `timeout=5, max=100`. Unknown or malformed parameters are ignored.
+fn parse_keep_alive(value: &str) -> (Option<Duration>, Option<u32>) {
+    let mut timeout = None;
+    let mut max = None;
+    for param in value.split(',') {
+        let (name, value) = match param.split_once('=') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => continue,
+        };
+        if name.eq_ignore_ascii_case("timeout") {
+            timeout = value.parse().ok().map(Duration::from_secs).or(timeout);
+        } else if name.eq_ignore_ascii_case("max") {
+            max = value.parse().ok().or(max);
+        }
+    }
+    (timeout, max)
+}
+
 impl FromStr for Response {
     type Err = Error;
     /// Parse a response from a string.
@@ -759,6 +1750,9 @@ impl FromStr for Response {
         let request_reader = SizedReader {
             size: crate::body::BodySize::Empty,
             reader: Box::new(std::io::empty()),
+            factory: None,
+            seekable: None,
+            checksum: None,
         };
         let unit = Unit::new(
             &Agent::new(),
@@ -772,21 +1766,22 @@ impl FromStr for Response {
     }
 }
 
-fn read_next_line(reader: &mut impl BufRead, context: &str) -> io::Result<HeaderLine> {
+fn read_next_line(
+    reader: &mut impl BufRead,
+    context: &str,
+    max_len: usize,
+) -> Result<HeaderLine, Error> {
     let mut buf = Vec::new();
     let result = reader
-        .take((MAX_HEADER_SIZE + 1) as u64)
+        .take((max_len + 1) as u64)
         .read_until(b'\n', &mut buf);
 
     match result {
-        Ok(0) => Err(io::Error::new(
-            io::ErrorKind::ConnectionAborted,
-            "Unexpected EOF",
-        )),
-        Ok(n) if n > MAX_HEADER_SIZE => Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!("header field longer than {} bytes", MAX_HEADER_SIZE),
-        )),
+        Ok(0) => Err(io::Error::new(io::ErrorKind::ConnectionAborted, "Unexpected EOF").into()),
+        Ok(n) if n > max_len => {
+            Err(ErrorKind::HeadersTooLarge
+                .msg(format!("header field longer than {} bytes", max_len)))
+        }
         Ok(_) => Ok(()),
         Err(e) => {
             // Provide context to errors encountered while reading the line.
@@ -798,7 +1793,7 @@ fn read_next_line(reader: &mut impl BufRead, context: &str) -> io::Result<Header
             // as well as a .source() reference to the original error.
             let wrapper = Error::new(ErrorKind::Io, Some(reason)).src(e);
 
-            Err(io::Error::new(kind, wrapper))
+            Err(io::Error::new(kind, wrapper).into())
         }
     }?;
 
@@ -806,7 +1801,8 @@ fn read_next_line(reader: &mut impl BufRead, context: &str) -> io::Result<Header
         return Err(io::Error::new(
             io::ErrorKind::InvalidInput,
             format!("Header field didn't end with \\n: {:?}", buf),
-        ));
+        )
+        .into());
     }
 
     buf.pop();
@@ -817,6 +1813,297 @@ fn read_next_line(reader: &mut impl BufRead, context: &str) -> io::Result<Header
     Ok(buf.into())
 }
 
+/// What it takes to read a body off the connection. The reader isn't built until the
+/// body is first read, so that [`Response::into_raw()`] can take over the connection.
+pub(crate) struct PendingBody {
+    stream: DeadlineStream,
+    unit: Unit,
+    body_type: BodyType,
+    compression: Option<Compression>,
+    connection_option: ConnectionOption,
+    required_trailers: Vec<String>,
+    remaining: Option<Arc<AtomicU64>>,
+}
+
+impl PendingBody {
+    fn new(
+        stream: DeadlineStream,
+        unit: Unit,
+        body_type: BodyType,
+        compression: Option<Compression>,
+        connection_option: ConnectionOption,
+        required_trailers: Vec<String>,
+    ) -> Self {
+        let remaining = match body_type {
+            BodyType::LengthDelimited(len) => Some(Arc::new(AtomicU64::new(len as u64))),
+            _ => None,
+        };
+        PendingBody {
+            stream,
+            unit,
+            body_type,
+            compression,
+            connection_option,
+            required_trailers,
+            remaining,
+        }
+    }
+
+    /// Whether the whole body is buffered already, including when there is no body.
+    fn is_buffered(&self) -> bool {
+        match self.body_type {
+            BodyType::LengthDelimited(len) => len <= self.stream.inner_ref().buffer().len(),
+            _ => false,
+        }
+    }
+
+    /// The chunk decoder for a chunked body, or the body back if it isn't chunked.
+    fn into_chunk_decoder(
+        self: Box<Self>,
+    ) -> Result<io::Result<ChunkDecoder<DeadlineStream>>, Box<Self>> {
+        if !matches!(self.body_type, BodyType::Chunked) {
+            return Err(self);
+        }
+        let PendingBody {
+            mut stream,
+            unit,
+            connection_option,
+            required_trailers,
+            ..
+        } = *self;
+
+        if connection_option == ConnectionOption::Close {
+            stream.inner_mut().set_unpoolable();
+        }
+        if let Err(e) = stream
+            .inner_ref()
+            .set_read_timeout(unit.agent.config.timeout_read)
+        {
+            return Ok(Err(e));
+        }
+        let mut decoder = ChunkDecoder::new(stream);
+        decoder.require_trailers(required_trailers);
+        Ok(Ok(decoder))
+    }
+
+    fn into_reader(self) -> Box<dyn Read + Send + Sync + 'static> {
+        let PendingBody {
+            mut stream,
+            unit,
+            body_type,
+            compression,
+            connection_option,
+            required_trailers,
+            remaining,
+        } = self;
+
+        if connection_option == ConnectionOption::Close {
+            stream.inner_mut().set_unpoolable();
+        }
+        let inner = stream.inner_ref();
+        let result = inner.set_read_timeout(unit.agent.config.timeout_read);
+        if let Err(e) = result {
+            return Box::new(ErrorReader(e));
+        }
+        let buffer_len = inner.buffer().len();
+
+        let body_reader: Box<dyn Read + Send + Sync> = match body_type {
+            // Chunked responses have an unknown length, but do have an end of body
+            // marker. When we encounter the marker, we can return the underlying stream
+            // to the connection pool.
+            BodyType::Chunked => {
+                debug!("Chunked body in response");
+                let mut decoder = ChunkDecoder::new(stream);
+                decoder.require_trailers(required_trailers);
+                Box::new(PoolReturnRead::new(decoder))
+            }
+            // Responses with a content-length header means we should limit the reading
+            // of the body to the number of bytes in the header. Once done, we can
+            // return the underlying stream to the connection pool.
+            BodyType::LengthDelimited(len) => {
+                match NonZeroUsize::new(len) {
+                    None => {
+                        debug!("zero-length body returning stream directly to pool");
+                        let stream: Stream = stream.into();
+                        // TODO: This expect can actually panic if we get an error when
+                        // returning the stream to the pool. We reset the read timeouts
+                        // when we do that, and since that's a syscall it can fail.
+                        stream.return_to_pool().expect("returning stream to pool");
+                        Box::new(std::io::empty())
+                    }
+                    Some(len) => {
+                        let mut limited_read = LimitedRead::new(stream, len);
+
+                        if len.get() <= buffer_len {
+                            debug!("Body entirely buffered (length: {})", len);
+                            let mut buf = vec![0; len.get()];
+                            // TODO: This expect can actually panic if we get an error when
+                            // returning the stream to the pool. We reset the read timeouts
+                            // when we do that, and since that's a syscall it can fail.
+                            limited_read
+                                .read_exact(&mut buf)
+                                .expect("failed to read exact buffer length from stream");
+                            Box::new(Cursor::new(buf))
+                        } else {
+                            debug!("Streaming body until content-length: {}", len);
+                            Box::new(limited_read)
+                        }
+                    }
+                }
+            }
+            BodyType::CloseDelimited => {
+                debug!("Body of unknown size - read until socket close");
+                Box::new(stream)
+            }
+        };
+
+        let body_reader: Box<dyn Read + Send + Sync> = match remaining {
+            Some(remaining) => Box::new(RemainingRead {
+                reader: body_reader,
+                remaining,
+            }),
+            None => body_reader,
+        };
+
+        let body_reader: Box<dyn Read + Send + Sync> = match unit.rate_limit {
+            Some(rate) => Box::new(Throttle::new(body_reader, rate)),
+            None => body_reader,
+        };
+
+        let config = &unit.agent.config;
+        match compression {
+            None => body_reader,
+            Some(c)
+                if config.max_decompressed_size.is_none()
+                    && config.max_compression_ratio.is_none() =>
+            {
+                c.wrap_reader(body_reader)
+            }
+            Some(c) => Box::new(DecompressionLimit::new(
+                c,
+                body_reader,
+                config.max_decompressed_size,
+                config.max_compression_ratio,
+            )),
+        }
+    }
+}
+
+/// Bytes read ahead by [`Response::image_dimensions`], which count down the bytes
+/// left of the body to what they were after reading ahead.
+struct PeekedRead {
+    head: Cursor<Vec<u8>>,
+    remaining: Arc<AtomicU64>,
+    after: u64,
+}
+
+impl Read for PeekedRead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.head.read(buf)?;
+        let left = self.remaining.load(Ordering::Relaxed);
+        let left = if self.head.position() == self.head.get_ref().len() as u64 {
+            self.after
+        } else {
+            left.saturating_sub(n as u64).max(self.after)
+        };
+        self.remaining.store(left, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+/// Counts down the bytes left of a length-delimited body, for [`Response::bytes_remaining`].
+struct RemainingRead {
+    reader: Box<dyn Read + Send + Sync>,
+    remaining: Arc<AtomicU64>,
+}
+
+impl Read for RemainingRead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.remaining.fetch_sub(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+/// Counts the bytes read through it.
+struct CountRead {
+    reader: Box<dyn Read + Send + Sync>,
+    count: Arc<AtomicU64>,
+}
+
+impl Read for CountRead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.count.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+// Small bodies can have high compression ratios, like a few kilobytes of
+// repetitive JSON. Only check the ratio past this decompressed size.
+const RATIO_CHECK_MIN_SIZE: u64 = 1024 * 1024;
+
+/// Fails reading a decompressed body once it's larger than `max_size`, or
+/// larger than `max_ratio` times the compressed body read so far.
+struct DecompressionLimit {
+    reader: Box<dyn Read + Send + Sync>,
+    compressed: Arc<AtomicU64>,
+    decompressed: u64,
+    max_size: Option<u64>,
+    max_ratio: Option<u64>,
+}
+
+impl DecompressionLimit {
+    fn new(
+        compression: Compression,
+        reader: Box<dyn Read + Send + Sync>,
+        max_size: Option<u64>,
+        max_ratio: Option<u64>,
+    ) -> Self {
+        let compressed = Arc::new(AtomicU64::new(0));
+        let reader = Box::new(CountRead {
+            reader,
+            count: compressed.clone(),
+        });
+        DecompressionLimit {
+            reader: compression.wrap_reader(reader),
+            compressed,
+            decompressed: 0,
+            max_size,
+            max_ratio,
+        }
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        if let Some(max) = self.max_size {
+            if self.decompressed > max {
+                return Err(ErrorKind::DecompressionBomb
+                    .msg(format!("body decompressed to more than {} bytes", max)));
+            }
+        }
+        if let Some(max) = self.max_ratio {
+            let compressed = self.compressed.load(Ordering::Relaxed).max(1);
+            if self.decompressed > RATIO_CHECK_MIN_SIZE && self.decompressed / compressed > max {
+                return Err(ErrorKind::DecompressionBomb.msg(format!(
+                    "body decompressed to more than {} times its compressed size",
+                    max
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Read for DecompressionLimit {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.decompressed += n as u64;
+        self.check()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(n)
+    }
+}
+
 /// Limits a `Read` to a content size (as set by a "Content-Length" header).
 pub(crate) struct LimitedRead<R> {
     reader: Option<R>,
@@ -864,44 +2151,358 @@ impl<R: Read + Sized + Into<Stream>> Read for LimitedRead<R> {
             None => return Ok(0),
             Some(r) => r,
         };
-        match reader.read(from) {
-            // https://tools.ietf.org/html/rfc7230#page-33
-            // If the sender closes the connection or
-            // the recipient times out before the indicated number of octets are
-            // received, the recipient MUST consider the message to be
-            // incomplete and close the connection.
-            // TODO: actually close the connection by dropping the stream
-            Ok(0) => Err(io::Error::new(
-                io::ErrorKind::UnexpectedEof,
-                "response body closed before all bytes were read",
-            )),
-            Ok(amount) => {
-                self.position += amount;
-                if self.remaining() == 0 {
-                    self.return_stream_to_pool()?;
-                }
-                Ok(amount)
+        match reader.read(from) {
+            // https://tools.ietf.org/html/rfc7230#page-33
+            // If the sender closes the connection or
+            // the recipient times out before the indicated number of octets are
+            // received, the recipient MUST consider the message to be
+            // incomplete and close the connection.
+            // TODO: actually close the connection by dropping the stream
+            Ok(0) => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "response body closed before all bytes were read",
+            )),
+            Ok(amount) => {
+                self.position += amount;
+                if self.remaining() == 0 {
+                    self.return_stream_to_pool()?;
+                }
+                Ok(amount)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Parse the value of a `Link` header (RFC 8288) into pairs of link target
+/// and `rel` parameter. Links without a `rel` parameter are skipped.
+fn parse_links(value: &str) -> Vec<(&str, &str)> {
+    let mut links = vec![];
+    let mut rest = value;
+    while let Some(start) = rest.find('<') {
+        let end = match rest[start..].find('>') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let target = rest[start + 1..end].trim();
+        rest = &rest[end + 1..];
+
+        // The parameters run until the next comma that is not inside quotes.
+        let mut in_quotes = false;
+        let params_end = rest
+            .char_indices()
+            .find(|&(_, c)| {
+                if c == '"' {
+                    in_quotes = !in_quotes;
+                }
+                c == ',' && !in_quotes
+            })
+            .map(|(i, _)| i)
+            .unwrap_or(rest.len());
+        let params = &rest[..params_end];
+        rest = &rest[params_end..];
+
+        let rel = params.split(';').find_map(|param| {
+            let (name, value) = param.split_once('=')?;
+            if name.trim().eq_ignore_ascii_case("rel") {
+                Some(value.trim().trim_matches('"'))
+            } else {
+                None
+            }
+        });
+        if let Some(rel) = rel {
+            links.push((target, rel));
+        }
+    }
+    links
+}
+
+/// Extract the charset from a "Content-Type" header.
+///
+/// "Content-Type: text/plain; charset=iso8859-1" -> "iso8859-1"
+///
+/// *Internal API*
+pub(crate) fn charset_from_content_type(header: Option<&str>) -> &str {
+    header
+        .and_then(|header| {
+            header.find(';').and_then(|semi| {
+                header[semi + 1..]
+                    .find('=')
+                    .map(|equal| header[semi + equal + 2..].trim())
+            })
+        })
+        .unwrap_or(DEFAULT_CHARACTER_SET)
+}
+
+/// Find the charset declared by a `<meta charset="...">` or
+/// `<meta http-equiv="Content-Type" content="...; charset=...">` tag.
+#[cfg(feature = "charset")]
+fn sniff_meta_charset(body: &[u8]) -> Option<&str> {
+    let lower = body.to_ascii_lowercase();
+    let mut pos = 0;
+    while let Some(start) = find(&lower[pos..], b"<meta").map(|i| pos + i) {
+        let end = find(&lower[start..], b">").map_or(lower.len(), |i| start + i);
+        let tag = &lower[start..end];
+        if let Some(i) = find(tag, b"charset") {
+            let value = tag[i + 7..]
+                .iter()
+                .position(|b| !b.is_ascii_whitespace())
+                .map(|j| i + 7 + j)
+                .filter(|&j| tag[j] == b'=')
+                .map(|j| j + 1);
+            if let Some(mut v) = value {
+                while v < tag.len()
+                    && (tag[v].is_ascii_whitespace() || tag[v] == b'"' || tag[v] == b'\'')
+                {
+                    v += 1;
+                }
+                let len = tag[v..]
+                    .iter()
+                    .position(|b| b" \t\r\n\"';/".contains(b))
+                    .unwrap_or(tag.len() - v);
+                let label = &body[start + v..start + v + len];
+                if !label.is_empty() {
+                    return std::str::from_utf8(label).ok();
+                }
+            }
+        }
+        pos = end;
+    }
+    None
+}
+
+#[cfg(feature = "charset")]
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// The iterator returned by [`Response::into_chunks()`].
+enum Chunks {
+    Chunked(Box<ChunkDecoder<DeadlineStream>>),
+    Whole(Box<dyn Read + Send + Sync + 'static>),
+    Failed(io::Error),
+    Done,
+}
+
+impl Iterator for Chunks {
+    type Item = Result<Vec<u8>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match std::mem::replace(self, Chunks::Done) {
+            Chunks::Chunked(mut decoder) => match read_chunk(&mut decoder) {
+                Ok(Some(chunk)) => {
+                    *self = Chunks::Chunked(decoder);
+                    Some(Ok(chunk))
+                }
+                Ok(None) => {
+                    let stream: Stream = decoder.into_inner().into();
+                    stream.return_to_pool().err().map(|e| Err(e.into()))
+                }
+                Err(e) => Some(Err(e.into())),
+            },
+            Chunks::Whole(mut reader) => {
+                let mut body = vec![];
+                match reader.read_to_end(&mut body) {
+                    Ok(0) => None,
+                    Ok(_) => Some(Ok(body)),
+                    Err(e) => Some(Err(e.into())),
+                }
+            }
+            Chunks::Failed(e) => Some(Err(e.into())),
+            Chunks::Done => None,
+        }
+    }
+}
+
+/// Read one whole chunk, or `None` at the terminating zero-length chunk.
+fn read_chunk(decoder: &mut ChunkDecoder<DeadlineStream>) -> io::Result<Option<Vec<u8>>> {
+    let mut chunk = vec![];
+    let mut buf = [0; 8 * 1024];
+    loop {
+        let n = decoder.read(&mut buf)?;
+        if n == 0 {
+            // The decoder only stops mid-chunk when the source runs dry.
+            if decoder.remaining_chunks_size().is_some() {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            return Ok(None);
+        }
+        chunk.extend_from_slice(&buf[..n]);
+        if decoder.remaining_chunks_size().is_none() {
+            return Ok(Some(chunk));
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+fn json_error(e: serde_json::Error) -> io::Error {
+    // This is to unify TimedOut io::Error in the API.
+    if let Some(kind) = e.io_error_kind() {
+        if kind == io::ErrorKind::TimedOut {
+            return stream::io_err_timeout(e.to_string());
+        }
+    }
+
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Failed to read JSON: {}", e),
+    )
+}
+
+/// The iterator returned by [`Response::into_json_array()`].
+#[cfg(feature = "json")]
+struct JsonArray<T> {
+    reader: io::BufReader<Box<dyn Read + Send + Sync + 'static>>,
+    /// The byte serde_json read past the end of a number, to see that it ended.
+    lookahead: Option<u8>,
+    state: JsonArrayState,
+    element: std::marker::PhantomData<fn() -> T>,
+}
+
+#[cfg(feature = "json")]
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum JsonArrayState {
+    /// Before the opening `[`.
+    Start,
+    /// After an element.
+    Separator,
+    /// After the closing `]`, or an error.
+    Done,
+}
+
+#[cfg(feature = "json")]
+fn is_json_whitespace(byte: u8) -> bool {
+    matches!(byte, b' ' | b'\t' | b'\n' | b'\r')
+}
+
+#[cfg(feature = "json")]
+impl<T> JsonArray<T> {
+    /// The next byte that isn't whitespace, left unread.
+    fn peek_token(&mut self) -> io::Result<u8> {
+        loop {
+            let byte = match self.reader.fill_buf()?.first() {
+                Some(byte) => *byte,
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "JSON array ended early",
+                    ))
+                }
+            };
+            if !is_json_whitespace(byte) {
+                return Ok(byte);
+            }
+            self.reader.consume(1);
+        }
+    }
+
+    /// The next byte that isn't whitespace.
+    fn next_token(&mut self) -> io::Result<u8> {
+        if let Some(byte) = self.lookahead.take() {
+            if !is_json_whitespace(byte) {
+                return Ok(byte);
+            }
+        }
+        let byte = self.peek_token()?;
+        self.reader.consume(1);
+        Ok(byte)
+    }
+
+    fn invalid(msg: &str) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Failed to read JSON: {}", msg),
+        )
+    }
+}
+
+#[cfg(feature = "json")]
+impl<T: DeserializeOwned> JsonArray<T> {
+    /// Move past the `[`, `,` and `]` around the elements. `Ok(false)` is the
+    /// end of the array.
+    fn advance(&mut self) -> io::Result<bool> {
+        if self.state == JsonArrayState::Start {
+            if self.next_token()? != b'[' {
+                return Err(Self::invalid("not an array"));
+            }
+            if self.peek_token()? == b']' {
+                self.reader.consume(1);
+                return Ok(false);
+            }
+        } else if self.state == JsonArrayState::Separator {
+            match self.next_token()? {
+                b',' => {}
+                b']' => return Ok(false),
+                _ => return Err(Self::invalid("expected `,` or `]` in array")),
+            }
+            if self.peek_token()? == b']' {
+                return Err(Self::invalid("trailing comma in array"));
+            }
+        }
+        Ok(true)
+    }
+
+    fn read_element(&mut self) -> io::Result<T> {
+        let number = matches!(self.peek_token()?, b'-' | b'0'..=b'9');
+        let mut reader = LastByte {
+            inner: &mut self.reader,
+            last: None,
+        };
+        let element = T::deserialize(&mut serde_json::Deserializer::from_reader(&mut reader))
+            .map_err(json_error)?;
+        // serde_json only sees a number is over when it reads the byte after
+        // it, which is then gone from the body.
+        if number {
+            self.lookahead = reader
+                .last
+                .filter(|&b| !matches!(b, b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E'));
+        }
+        Ok(element)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<T: DeserializeOwned> Iterator for JsonArray<T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.state == JsonArrayState::Done {
+            return None;
+        }
+        let element = match self.advance() {
+            Ok(true) => self.read_element(),
+            Ok(false) => {
+                self.state = JsonArrayState::Done;
+                return None;
             }
             Err(e) => Err(e),
-        }
+        };
+        // serde_json stops part way into an element it can't deserialize, with
+        // no telling where the next one starts.
+        self.state = match element {
+            Ok(_) => JsonArrayState::Separator,
+            Err(_) => JsonArrayState::Done,
+        };
+        Some(element.map_err(Error::from))
     }
 }
 
-/// Extract the charset from a "Content-Type" header.
-///
-/// "Content-Type: text/plain; charset=iso8859-1" -> "iso8859-1"
-///
-/// *Internal API*
-pub(crate) fn charset_from_content_type(header: Option<&str>) -> &str {
-    header
-        .and_then(|header| {
-            header.find(';').and_then(|semi| {
-                header[semi + 1..]
-                    .find('=')
-                    .map(|equal| header[semi + equal + 2..].trim())
-            })
-        })
-        .unwrap_or(DEFAULT_CHARACTER_SET)
+/// Remembers the last byte read through it.
+#[cfg(feature = "json")]
+struct LastByte<'a, R> {
+    inner: &'a mut R,
+    last: Option<u8>,
+}
+
+#[cfg(feature = "json")]
+impl<R: Read> Read for LastByte<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            self.last = Some(buf[n - 1]);
+        }
+        Ok(n)
+    }
 }
 
 // ErrorReader returns an error for every read.
@@ -923,6 +2524,31 @@ mod tests {
 
     use super::*;
 
+    // Read a response from `bytes`, as if it arrived on a connection of `agent`.
+    fn read_response(agent: &Agent, bytes: &[u8]) -> Result<Response, Error> {
+        let test_stream = crate::test::TestStream::new(Cursor::new(bytes.to_vec()), io::sink());
+        let stream = Stream::new(
+            test_stream,
+            "1.1.1.1:4343".parse().unwrap(),
+            PoolReturner::none(),
+        );
+        Response::do_from_stream(
+            stream,
+            Unit::new(
+                agent,
+                "GET",
+                &"https://example.com/".parse().unwrap(),
+                vec![],
+                &Payload::Empty.into_read(),
+                None,
+            ),
+        )
+    }
+
+    fn response_from_bytes(bytes: &[u8]) -> Response {
+        read_response(&Agent::new(), bytes).unwrap()
+    }
+
     #[test]
     fn short_read() {
         use std::io::Cursor;
@@ -948,6 +2574,89 @@ mod tests {
         assert_eq!("application/json", resp.content_type());
     }
 
+    #[test]
+    fn was_decompressed() {
+        let s = "HTTP/1.1 200 OK\r\n\r\nplain";
+        assert_eq!(s.parse::<Response>().unwrap().was_decompressed(), None);
+
+        let s = "HTTP/1.1 200 OK\r\nContent-Encoding: zstd\r\n\r\n";
+        let resp = s.parse::<Response>().unwrap();
+        assert_eq!(resp.content_encoding(), Some("zstd"));
+        assert_eq!(resp.was_decompressed(), None);
+
+        #[cfg(feature = "gzip")]
+        {
+            let s = "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\n\r\n";
+            let resp = s.parse::<Response>().unwrap();
+            assert_eq!(resp.was_decompressed(), Some("gzip"));
+        }
+    }
+
+    #[test]
+    fn content_type_params() {
+        let s = "HTTP/1.1 200 OK\r\n\
+                 Content-Type: multipart/mixed; boundary=\"abc\"; foo=bar\r\n\
+                 \r\n";
+        let params = s.parse::<Response>().unwrap().content_type_params();
+        assert_eq!(params.len(), 2);
+        assert_eq!(params["boundary"], "abc");
+        assert_eq!(params["foo"], "bar");
+
+        let params = parse_content_type_params(r#"text/plain; Q="a;\"b\""; flowed; c = d"#);
+        assert_eq!(params["q"], r#"a;"b""#);
+        assert_eq!(params["c"], "d");
+        assert_eq!(params.len(), 2);
+
+        assert!(parse_content_type_params("text/plain").is_empty());
+    }
+
+    #[test]
+    fn header_names() {
+        let s = "HTTP/1.1 200 OK\r\n\
+                 A: 1\r\n\
+                 B: 2\r\n\
+                 a: 3\r\n\
+                 \r\n";
+        let resp = s.parse::<Response>().unwrap();
+        assert_eq!(resp.header_count(), 3);
+        assert_eq!(resp.header_names(), vec!["A", "B"]);
+    }
+
+    #[test]
+    fn range_start() {
+        let s = "HTTP/1.1 206 Partial Content\r\n\
+                 Content-Range: bytes 500-999/2000\r\n\
+                 \r\n";
+        assert_eq!(s.parse::<Response>().unwrap().range_start(), Some(500));
+
+        let s = "HTTP/1.1 200 OK\r\n\
+                 Content-Range: bytes 500-999/2000\r\n\
+                 \r\n";
+        assert_eq!(s.parse::<Response>().unwrap().range_start(), None);
+
+        let s = "HTTP/1.1 206 Partial Content\r\n\
+                 Content-Type: multipart/byteranges; boundary=x\r\n\
+                 \r\n";
+        assert_eq!(s.parse::<Response>().unwrap().range_start(), None);
+    }
+
+    #[test]
+    fn into_parts() {
+        let s = "HTTP/1.1 200 OK\r\n\
+                 Content-Type: text/plain\r\n\
+                 Content-Length: 5\r\n\
+                 \r\n\
+                 hello";
+        let resp = s.parse::<Response>().unwrap();
+        let (head, mut reader) = resp.into_parts();
+        let mut body = String::new();
+        reader.read_to_string(&mut body).unwrap();
+        assert_eq!(head.status(), 200);
+        assert_eq!(head.status_text(), "OK");
+        assert_eq!(head.header("content-type"), Some("text/plain"));
+        assert_eq!(body, "hello");
+    }
+
     #[test]
     fn content_type_without_cr() {
         let s = "HTTP/1.1 200 OK\r\n\
@@ -1010,6 +2719,101 @@ mod tests {
         assert_eq!("hello world!!!", resp.into_string().unwrap());
     }
 
+    #[test]
+    fn into_chunks() {
+        let s = "HTTP/1.1 200 OK\r\n\
+                 Transfer-Encoding: chunked\r\n\
+                 \r\n\
+                 3\r\n\
+                 hel\r\n\
+                 b\r\n\
+                 lo world!!!\r\n\
+                 1\r\n\
+                 ?\r\n\
+                 0\r\n\
+                 \r\n";
+        let resp = s.parse::<Response>().unwrap();
+        let sizes: Vec<_> = resp.into_chunks().map(|c| c.unwrap().len()).collect();
+        assert_eq!(sizes, vec![3, 11, 1]);
+    }
+
+    #[test]
+    fn is_empty_body() {
+        let resp = "HTTP/1.1 204 No Content\r\n\r\n"
+            .parse::<Response>()
+            .unwrap();
+        assert!(resp.is_empty_body());
+        let resp = "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n"
+            .parse::<Response>()
+            .unwrap();
+        assert!(resp.is_empty_body());
+        let resp = "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello"
+            .parse::<Response>()
+            .unwrap();
+        assert!(!resp.is_empty_body());
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn into_json_array() {
+        #[derive(serde::Deserialize, Debug, PartialEq)]
+        struct Item {
+            name: String,
+        }
+
+        let s = "HTTP/1.1 200 OK\r\n\r\n\
+                 [{\"name\": \"a\"}, {\"name\": \"b,]}\"}, {\"name\": \"c\", \"tags\": [1]}]";
+        let resp = s.parse::<Response>().unwrap();
+        let mut items = resp.into_json_array::<Item>();
+        assert_eq!(items.next().unwrap().unwrap().name, "a");
+        assert_eq!(items.next().unwrap().unwrap().name, "b,]}");
+        assert_eq!(items.next().unwrap().unwrap().name, "c");
+        assert!(items.next().is_none());
+
+        let s = "HTTP/1.1 200 OK\r\n\r\n[1,2 ,\n3]";
+        let resp = s.parse::<Response>().unwrap();
+        let items: Vec<_> = resp.into_json_array::<u32>().map(|r| r.ok()).collect();
+        assert_eq!(items, vec![Some(1), Some(2), Some(3)]);
+
+        // A bad element ends the iteration.
+        let s = "HTTP/1.1 200 OK\r\n\r\n[1, \"two\", 3]";
+        let resp = s.parse::<Response>().unwrap();
+        let items: Vec<_> = resp.into_json_array::<u32>().map(|r| r.ok()).collect();
+        assert_eq!(items, vec![Some(1), None]);
+
+        for s in ["[1, 2,]", "[1,, 2]", "[1 2]", "[1, 2"] {
+            let resp = format!("HTTP/1.1 200 OK\r\n\r\n{}", s)
+                .parse::<Response>()
+                .unwrap();
+            let items: Vec<_> = resp.into_json_array::<u32>().map(|r| r.ok()).collect();
+            assert_eq!(items.last(), Some(&None), "{}", s);
+        }
+
+        let s = "HTTP/1.1 200 OK\r\n\r\n [ ] ";
+        let resp = s.parse::<Response>().unwrap();
+        assert_eq!(resp.into_json_array::<u32>().count(), 0);
+    }
+
+    #[test]
+    fn header_joined() {
+        let s = "HTTP/1.1 200 OK\r\n\
+                 Cache-Control: no-cache\r\n\
+                 Set-Cookie: a=1; Expires=Wed, 21 Oct 2015 07:28:00 GMT\r\n\
+                 cache-control: max-age=0\r\n\
+                 Set-Cookie: b=2\r\n\
+                 \r\n";
+        let resp = s.parse::<Response>().unwrap();
+        assert_eq!(
+            resp.header_joined("Cache-Control").as_deref(),
+            Some("no-cache, max-age=0")
+        );
+        assert_eq!(
+            resp.header_joined("set-cookie").as_deref(),
+            Some("a=1; Expires=Wed, 21 Oct 2015 07:28:00 GMT")
+        );
+        assert_eq!(resp.header_joined("Via"), None);
+    }
+
     #[test]
     fn into_string_large() {
         const LEN: usize = INTO_STRING_LIMIT + 1;
@@ -1068,6 +2872,80 @@ mod tests {
         assert_eq!(err.kind(), BadStatus);
     }
 
+    #[test]
+    fn byteranges() {
+        let s = "HTTP/1.1 206 Partial Content\r\n\
+            Content-Type: multipart/byteranges; boundary=\"SEP\"\r\n\
+            \r\n\
+            --SEP\r\n\
+            Content-Type: text/plain\r\n\
+            Content-Range: bytes 0-4/26\r\n\
+            \r\n\
+            abcde\r\n\
+            --SEP\r\n\
+            Content-Range: bytes 10-16/26\r\n\
+            \r\n\
+            k--SEPq\r\n\
+            --SEP--\r\n"
+            .to_string();
+        let resp = s.parse::<Response>().unwrap();
+        let parts: Vec<_> = resp.byteranges().unwrap().collect();
+        assert_eq!(
+            parts,
+            vec![(0, 4, b"abcde".to_vec()), (10, 16, b"k--SEPq".to_vec())]
+        );
+
+        let s = "HTTP/1.1 206 Partial Content\r\n\
+            Content-Range: bytes 5-9/26\r\n\
+            \r\n\
+            fghij"
+            .to_string();
+        let resp = s.parse::<Response>().unwrap();
+        let parts: Vec<_> = resp.byteranges().unwrap().collect();
+        assert_eq!(parts, vec![(5, 9, b"fghij".to_vec())]);
+
+        let s = "HTTP/1.1 206 Partial Content\r\n\
+            Content-Type: multipart/byteranges; boundary=SEP\r\n\
+            \r\n\
+            --SEP\r\n\
+            Content-Range: bytes 0-9/26\r\n\
+            \r\n\
+            abc"
+        .to_string();
+        let resp = s.parse::<Response>().unwrap();
+        assert!(resp.byteranges().is_err());
+    }
+
+    #[test]
+    fn keep_alive_params() {
+        assert_eq!(
+            parse_keep_alive("timeout=5, max=100"),
+            (Some(Duration::from_secs(5)), Some(100))
+        );
+        assert_eq!(parse_keep_alive("MAX=1"), (None, Some(1)));
+        assert_eq!(parse_keep_alive("timeout=-1, max, foo=bar"), (None, None));
+    }
+
+    #[test]
+    fn parse_status_line_extra_spaces() {
+        let s = "HTTP/1.1  999   Weird\r\n\r\n".to_string();
+        let resp = s.parse::<Response>().unwrap();
+        assert_eq!(resp.http_version(), "HTTP/1.1");
+        assert_eq!(resp.status(), 999);
+        assert_eq!(resp.raw_status_bytes(), b"999");
+        assert_eq!(resp.status_text(), "Weird");
+
+        for s in [
+            "HTTP/1.1 +99 OK",
+            "HTTP/1.1 2000 OK",
+            "HTTP/1.1  ",
+            "HTTP/1.1",
+        ] {
+            let err = parse_status_line(s).unwrap_err();
+            assert_eq!(err.kind(), BadStatus, "{}", s);
+        }
+    }
+
     #[test]
     fn parse_header_without_reason() {
         let s = "HTTP/1.1 302\r\n\r\n".to_string();
@@ -1077,18 +2955,33 @@ mod tests {
 
     #[test]
     fn read_next_line_large() {
-        const LEN: usize = MAX_HEADER_SIZE + 1;
+        const LEN: usize = DEFAULT_MAX_HEADER_LINE_LENGTH + 1;
         let s = format!("Long-Header: {}\r\n", "A".repeat(LEN),);
         let mut cursor = Cursor::new(s);
-        let result = read_next_line(&mut cursor, "some context");
+        let result = read_next_line(&mut cursor, "some context", DEFAULT_MAX_HEADER_LINE_LENGTH);
         let err = result.expect_err("did not error on too-large header");
-        assert_eq!(err.kind(), io::ErrorKind::Other);
+        assert_eq!(err.kind(), ErrorKind::HeadersTooLarge);
         assert_eq!(
             err.to_string(),
-            format!("header field longer than {} bytes", MAX_HEADER_SIZE)
+            format!(
+                "Headers Too Large: header field longer than {} bytes",
+                DEFAULT_MAX_HEADER_LINE_LENGTH
+            )
         );
     }
 
+    #[test]
+    fn max_header_line_length() {
+        let read = |max: usize| {
+            let text = format!("HTTP/1.1 200 OK\r\nX-Long: {}\r\n\r\n", "A".repeat(100));
+            let agent = crate::builder().max_header_line_length(max).build();
+            read_response(&agent, text.as_bytes())
+        };
+        assert!(read(110).is_ok());
+        let err = read(100).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::HeadersTooLarge);
+    }
+
     #[test]
     fn too_many_headers() {
         const LEN: usize = MAX_HEADER_COUNT + 1;
@@ -1112,6 +3005,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn too_many_interim_responses() {
+        let interim = "HTTP/1.1 100 Continue\r\n\r\n";
+        let final_response = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhi";
+
+        let s = interim.repeat(MAX_INTERIM_RESPONSES) + final_response;
+        let resp = s.parse::<Response>().unwrap();
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.into_string().unwrap(), "hi");
+
+        let s = interim.repeat(MAX_INTERIM_RESPONSES + 1) + final_response;
+        let err = s.parse::<Response>().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BadStatus);
+    }
+
     #[test]
     #[cfg(feature = "charset")]
     fn read_next_line_non_ascii_reason() {
@@ -1119,7 +3027,11 @@ mod tests {
             encoding_rs::WINDOWS_1252.encode("HTTP/1.1 302 Déplacé Temporairement\r\n");
         let bytes = cow.to_vec();
         let mut reader = io::BufReader::new(io::Cursor::new(bytes));
-        let r = read_next_line(&mut reader, "test status line");
+        let r = read_next_line(
+            &mut reader,
+            "test status line",
+            DEFAULT_MAX_HEADER_LINE_LENGTH,
+        );
         let h = r.unwrap();
         assert_eq!(h.to_string(), "HTTP/1.1 302 D�plac� Temporairement");
     }
@@ -1143,6 +3055,9 @@ mod tests {
         let request_reader = SizedReader {
             size: crate::body::BodySize::Empty,
             reader: Box::new(std::io::empty()),
+            factory: None,
+            seekable: None,
+            checksum: None,
         };
         let unit = Unit::new(
             &Agent::new(),
@@ -1254,6 +3169,230 @@ mod tests {
         assert_eq!(body, "hi\n");
     }
 
+    #[cfg(feature = "gzip")]
+    fn read_gzip_zeros(agent: &Agent, len: usize) -> (Vec<u8>, io::Result<usize>) {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&vec![0; len]).unwrap();
+        let gz = encoder.finish().unwrap();
+        let mut response_bytes = format!(
+            "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+            gz.len()
+        )
+        .into_bytes();
+        response_bytes.extend_from_slice(&gz);
+
+        let resp = read_response(agent, &response_bytes).unwrap();
+        let mut body = vec![];
+        let result = resp.into_reader().read_to_end(&mut body);
+        (body, result)
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn decompression_bomb() {
+        fn kind(err: io::Error) -> ErrorKind {
+            err.into_inner()
+                .and_then(|e| e.downcast::<Error>().ok())
+                .map(|e| e.kind())
+                .unwrap()
+        }
+
+        let agent = crate::builder().max_decompressed_size(100_000).build();
+        let (body, result) = read_gzip_zeros(&agent, 4 * 1024 * 1024);
+        assert_eq!(kind(result.unwrap_err()), ErrorKind::DecompressionBomb);
+        assert!(body.len() <= 100_000);
+
+        let (body, result) = read_gzip_zeros(&agent, 100_000);
+        assert_eq!(result.unwrap(), 100_000);
+        assert_eq!(body.len(), 100_000);
+
+        let agent = crate::builder().max_compression_ratio(100).build();
+        let (body, result) = read_gzip_zeros(&agent, 4 * 1024 * 1024);
+        assert_eq!(kind(result.unwrap_err()), ErrorKind::DecompressionBomb);
+        assert!(body.len() < 4 * 1024 * 1024);
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn gzip_chunked_encodings() {
+        let response_bytes: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\n\
+Transfer-Encoding: chunked\r\n\r\n17\r\n\
+\x1f\x8b\x08\x00\x00\x00\x00\x00\x00\x03\xcb\xc8\xe4\x02\x00\x7a\x7a\x6f\xed\x03\x00\x00\x00\
+\r\n0\r\n\r\n";
+        let resp = response_from_bytes(response_bytes);
+        assert_eq!(resp.content_encoding(), Some("gzip"));
+        assert_eq!(resp.transfer_encoding(), Some("chunked"));
+        assert!(!resp.has("content-encoding"));
+        assert_eq!(resp.into_string().unwrap(), "hi\n");
+    }
+
+    #[test]
+    fn concatenated_responses_bounded_by_content_length() {
+        let response_bytes: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst\
+HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecond";
+        let resp = response_from_bytes(response_bytes);
+        assert_eq!(resp.into_string().unwrap(), "first");
+    }
+
+    #[test]
+    fn drain_counts_remaining() {
+        let body = "x".repeat(110);
+        let text = format!("HTTP/1.1 200 OK\r\nContent-Length: 110\r\n\r\n{}", body);
+        let mut resp = text.parse::<Response>().unwrap();
+        let mut buf = [0; 10];
+        resp.reader.read_exact(&mut buf).unwrap();
+        assert_eq!(resp.drain().unwrap(), 100);
+        assert_eq!(resp.drain().unwrap(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn json_pointer() {
+        let text = "HTTP/1.1 200 OK\r\n\r\n{\"data\": [{\"id\": 42}, {\"id\": 43}]}";
+        let resp = text.parse::<Response>().unwrap();
+        assert_eq!(resp.json_pointer("/data/0/id").unwrap(), Some(42.into()));
+        let resp = text.parse::<Response>().unwrap();
+        assert_eq!(resp.json_pointer("/data/2/id").unwrap(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "charset")]
+    fn charset_sniffing() {
+        let body: &[u8] = b"<html><head><meta http-equiv=\"Content-Type\" \
+content=\"text/html; charset=ISO-8859-1\"></head><body>\xe5\xe4\xf6</body></html>";
+        for (sniff, expected) in [
+            (true, "\u{e5}\u{e4}\u{f6}"),
+            (false, "\u{fffd}\u{fffd}\u{fffd}"),
+        ] {
+            let mut bytes = b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n".to_vec();
+            bytes.extend_from_slice(body);
+            let agent = crate::builder().charset_sniffing(sniff).build();
+            let resp = read_response(&agent, &bytes).unwrap();
+            let text = resp.into_string().unwrap();
+            assert!(text.starts_with("<html>"));
+            assert!(text.contains(expected));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "charset")]
+    fn sniff_meta_charsets() {
+        assert_eq!(
+            sniff_meta_charset(b"<META CharSet = 'Shift_JIS' />"),
+            Some("Shift_JIS")
+        );
+        assert_eq!(sniff_meta_charset(b"<meta charset=utf-8>"), Some("utf-8"));
+        assert_eq!(sniff_meta_charset(b"<meta name=\"charset\">"), None);
+        assert_eq!(sniff_meta_charset(b"<p>charset=latin1</p>"), None);
+    }
+
+    #[test]
+    fn strict_trailers() {
+        fn read(strict: bool, trailers: &str) -> io::Result<String> {
+            let text = format!(
+                "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nTrailer: X-Checksum\r\n\r\n\
+                5\r\nhello\r\n0\r\n{}\r\n",
+                trailers
+            );
+            let agent = crate::builder().strict_trailers(strict).build();
+            read_response(&agent, text.as_bytes())
+                .unwrap()
+                .into_string()
+        }
+
+        assert_eq!(read(false, "").unwrap(), "hello");
+        assert_eq!(read(true, "x-checksum: 1234\r\n").unwrap(), "hello");
+        let err = read(true, "").unwrap_err();
+        let err = err.into_inner().unwrap().downcast::<Error>().unwrap();
+        assert_eq!(err.kind(), ErrorKind::MissingTrailer);
+    }
+
+    #[test]
+    fn bytes_remaining() {
+        let s = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n{}",
+            "a".repeat(100)
+        );
+        let mut resp: Response = s.parse().unwrap();
+        assert_eq!(resp.bytes_remaining(), Some(100));
+        resp.reader.read_exact(&mut [0; 30]).unwrap();
+        assert_eq!(resp.bytes_remaining(), Some(70));
+        resp.drain().unwrap();
+        assert_eq!(resp.bytes_remaining(), Some(0));
+
+        let s = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n";
+        let resp: Response = s.parse().unwrap();
+        assert_eq!(resp.bytes_remaining(), None);
+    }
+
+    #[test]
+    fn image_dimensions() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&16_u32.to_be_bytes());
+        png.extend_from_slice(&9_u32.to_be_bytes());
+        png.extend_from_slice(&[7; 1000]);
+        let mut text = b"HTTP/1.1 200 OK\r\nContent-Type: image/png\r\n\r\n".to_vec();
+        text.extend_from_slice(&png);
+
+        let mut resp = response_from_bytes(&text);
+        assert_eq!(resp.image_dimensions().unwrap(), Some((16, 9)));
+        let mut body = vec![];
+        resp.into_reader().read_to_end(&mut body).unwrap();
+        assert_eq!(body, png);
+
+        let mut resp: Response = "HTTP/1.1 200 OK\r\n\r\n<html>".parse().unwrap();
+        assert_eq!(resp.image_dimensions().unwrap(), None);
+        assert_eq!(resp.into_string().unwrap(), "<html>");
+    }
+
+    #[test]
+    fn image_dimensions_keeps_bytes_remaining() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&16_u32.to_be_bytes());
+        png.extend_from_slice(&9_u32.to_be_bytes());
+        png.extend_from_slice(&[7; 2000]);
+        let mut text =
+            format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", png.len()).into_bytes();
+        text.extend_from_slice(&png);
+
+        let mut resp = response_from_bytes(&text);
+        let len = png.len() as u64;
+        assert_eq!(resp.image_dimensions().unwrap(), Some((16, 9)));
+        assert_eq!(resp.bytes_remaining(), Some(len));
+        resp.reader.read_exact(&mut [0; 10]).unwrap();
+        assert_eq!(resp.bytes_remaining(), Some(len - 10));
+        assert_eq!(resp.drain().unwrap(), len - 10);
+        assert_eq!(resp.bytes_remaining(), Some(0));
+    }
+
+    #[test]
+    fn image_dimensions_read_error() {
+        // The body ends before the announced length.
+        let text = b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n\x89PNG\r\n";
+        let mut resp = response_from_bytes(text);
+        let err = resp.image_dimensions().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn links() {
+        let s = "HTTP/1.1 200 OK\r\n\
+            Link: <a>; rel=\"next\", <b>; rel=\"prev\"\r\n\
+            Link: <https://other.com/x,y>; title=\"a, b\"; rel=\"last Alternate\"\r\n\
+            Link: <c>; rel=next\r\n\
+            \r\n";
+        let resp: Response = s.parse().unwrap();
+        let links = resp.links();
+        assert_eq!(links.len(), 4);
+        assert_eq!(links["next"], "https://example.com/a");
+        assert_eq!(links["prev"], "https://example.com/b");
+        assert_eq!(links["last"], "https://other.com/x,y");
+        assert_eq!(links["alternate"], "https://other.com/x,y");
+    }
+
     #[test]
     fn connection_option() {
         use ConnectionOption::*;
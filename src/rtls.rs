@@ -33,6 +33,10 @@ impl ReadWrite for RustlsStream {
     fn socket(&self) -> Option<&TcpStream> {
         self.0.get_ref().socket()
     }
+
+    fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.0.conn.alpn_protocol()
+    }
 }
 
 // TODO: After upgrading to rustls 0.20 or higher, we can remove these Read
@@ -140,14 +144,19 @@ impl TlsConnector for Arc<rustls::ClientConfig> {
 }
 
 pub fn default_tls_config() -> Arc<dyn TlsConnector> {
-    static TLS_CONF: Lazy<Arc<dyn TlsConnector>> = Lazy::new(|| {
+    static TLS_CONF: Lazy<Arc<dyn TlsConnector>> = Lazy::new(|| Arc::new(default_client_config()));
+    TLS_CONF.clone()
+}
+
+pub(crate) fn default_client_config() -> Arc<rustls::ClientConfig> {
+    static CLIENT_CONF: Lazy<Arc<rustls::ClientConfig>> = Lazy::new(|| {
         let config = rustls::ClientConfig::builder()
             .with_safe_defaults()
             .with_root_certificates(root_certs())
             .with_no_client_auth();
-        Arc::new(Arc::new(config))
+        Arc::new(config)
     });
-    TLS_CONF.clone()
+    CLIENT_CONF.clone()
 }
 
 impl fmt::Debug for RustlsStream {
@@ -2,6 +2,8 @@ use log::debug;
 use std::io::{self, BufRead, BufReader, Read, Write};
 use std::net::SocketAddr;
 use std::net::TcpStream;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
 use std::time::Duration;
 use std::time::Instant;
 use std::{fmt, io::Cursor};
@@ -11,7 +13,7 @@ use socks::{TargetAddr, ToTargetAddr};
 
 use crate::chunked::Decoder as ChunkDecoder;
 use crate::error::ErrorKind;
-use crate::pool::{PoolKey, PoolReturner};
+use crate::pool::{ConnectionPermit, PoolKey, PoolReturner};
 use crate::proxy::Proxy;
 use crate::unit::Unit;
 use crate::{error::Error, proxy::Proto};
@@ -19,6 +21,11 @@ use crate::{error::Error, proxy::Proto};
 /// Trait for things implementing [std::io::Read] + [std::io::Write]. Used in [TlsConnector].
 pub trait ReadWrite: Read + Write + Send + Sync + fmt::Debug + 'static {
     fn socket(&self) -> Option<&TcpStream>;
+
+    /// The application protocol agreed on with ALPN during the TLS handshake, if any.
+    fn alpn_protocol(&self) -> Option<&[u8]> {
+        None
+    }
 }
 
 impl ReadWrite for TcpStream {
@@ -40,12 +47,125 @@ pub(crate) struct Stream {
     /// The remote address the stream is connected to.
     pub(crate) remote_addr: SocketAddr,
     pool_returner: PoolReturner,
+    /// Counts this stream against the agent's limit on open connections.
+    permit: Option<ConnectionPermit>,
+    /// The number of responses received on this stream.
+    responses: u32,
+    /// How long the server keeps the stream open while idle, from the `Keep-Alive` header.
+    keep_alive_timeout: Option<Duration>,
+    /// The tap of the current request, once `inner` is wrapped in a `TapStream`.
+    tap: Option<Arc<Mutex<Option<Tap>>>>,
+}
+
+/// Which way bytes went, for [`Request::tap`](crate::Request::tap).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Bytes written to the server.
+    Write,
+    /// Bytes read from the server.
+    Read,
+}
+
+pub(crate) type Tap = Arc<dyn Fn(Direction, &[u8]) + Send + Sync + 'static>;
+
+/// Bytes read ahead of a connection, which are read again before the rest of it.
+#[derive(Debug)]
+struct Prefixed {
+    prefix: Cursor<Vec<u8>>,
+    inner: Box<dyn ReadWrite>,
+}
+
+impl Read for Prefixed {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.prefix.position() < self.prefix.get_ref().len() as u64 {
+            return self.prefix.read(buf);
+        }
+        self.inner.read(buf)
+    }
+}
+
+impl Write for Prefixed {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl ReadWrite for Prefixed {
+    fn socket(&self) -> Option<&TcpStream> {
+        self.inner.socket()
+    }
+
+    fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.inner.alpn_protocol()
+    }
+}
+
+/// Shows the bytes going through the wrapped stream to the tap in `slot`.
+///
+/// The wrapper stays when the stream goes back to the pool, with the slot
+/// emptied, so the next request can set its own tap.
+struct TapStream {
+    inner: Box<dyn ReadWrite>,
+    slot: Arc<Mutex<Option<Tap>>>,
+}
+
+impl TapStream {
+    fn tap(&self, direction: Direction, bytes: &[u8]) {
+        let tap = self.slot.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        if let Some(tap) = tap {
+            tap(direction, bytes);
+        }
+    }
+}
+
+impl Read for TapStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.tap(Direction::Read, &buf[..n]);
+        Ok(n)
+    }
+}
+
+impl Write for TapStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.tap(Direction::Write, &buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl ReadWrite for TapStream {
+    fn socket(&self) -> Option<&TcpStream> {
+        self.inner.socket()
+    }
+
+    fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.inner.alpn_protocol()
+    }
+}
+
+impl fmt::Debug for TapStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TapStream({:?})", self.inner)
+    }
 }
 
 impl<T: ReadWrite + ?Sized> ReadWrite for Box<T> {
     fn socket(&self) -> Option<&TcpStream> {
         ReadWrite::socket(self.as_ref())
     }
+
+    fn alpn_protocol(&self) -> Option<&[u8]> {
+        ReadWrite::alpn_protocol(self.as_ref())
+    }
 }
 
 // DeadlineStream wraps a stream such that read() will return an error
@@ -192,6 +312,10 @@ impl Stream {
             inner: BufReader::new(Box::new(t)),
             remote_addr,
             pool_returner,
+            permit: None,
+            responses: 0,
+            keep_alive_timeout: None,
+            tap: None,
         })
     }
 
@@ -204,6 +328,14 @@ impl Stream {
         self.inner.buffer()
     }
 
+    /// Take the bytes read ahead into the buffer, and the underlying connection.
+    pub(crate) fn into_raw(mut self) -> (Vec<u8>, Box<dyn ReadWrite>) {
+        let buffered = self.inner.buffer().to_vec();
+        let placeholder = BufReader::new(Box::new(ReadOnlyStream::new(vec![])) as _);
+        let inner = std::mem::replace(&mut self.inner, placeholder);
+        (buffered, inner.into_inner())
+    }
+
     // Check if the server has closed a stream by performing a one-byte
     // non-blocking read. If this returns EOF, the server has closed the
     // connection: return true. If this returns a successful read, there are
@@ -236,6 +368,10 @@ impl Stream {
 
         result
     }
+    pub(crate) fn inner_ref(&self) -> &dyn ReadWrite {
+        self.inner.get_ref()
+    }
+
     // Return true if the server has closed this connection.
     pub(crate) fn server_closed(&self) -> io::Result<bool> {
         match self.socket() {
@@ -244,6 +380,78 @@ impl Stream {
         }
     }
 
+    pub(crate) fn hold_permit(&mut self, permit: ConnectionPermit) {
+        self.permit = Some(permit);
+    }
+
+    /// Count a response received on this stream, returning how many there have been.
+    pub(crate) fn count_response(&mut self) -> u32 {
+        self.responses += 1;
+        self.responses
+    }
+
+    pub(crate) fn set_keep_alive_timeout(&mut self, timeout: Option<Duration>) {
+        self.keep_alive_timeout = timeout;
+    }
+
+    /// Whether the server has likely closed the stream after it was idle for `idle`.
+    pub(crate) fn keep_alive_expired(&self, idle: Duration) -> bool {
+        matches!(self.keep_alive_timeout, Some(timeout) if idle >= timeout)
+    }
+
+    /// Show the bytes read and written from now on to `tap`, or stop with `None`.
+    pub(crate) fn set_tap(&mut self, tap: Option<Tap>) {
+        if let Some(slot) = &self.tap {
+            *slot.lock().unwrap_or_else(|e| e.into_inner()) = tap;
+        } else if tap.is_some() {
+            let slot = Arc::new(Mutex::new(tap));
+            let placeholder: Box<dyn ReadWrite> = Box::new(ReadOnlyStream::new(vec![]));
+            let inner = std::mem::replace(self.inner.get_mut(), placeholder);
+            *self.inner.get_mut() = Box::new(TapStream {
+                inner,
+                slot: slot.clone(),
+            });
+            self.tap = Some(slot);
+        }
+    }
+
+    /// Read until the buffer holds a whole line, or at least `max` bytes, without
+    /// consuming it. The buffer is empty at the end of the stream.
+    pub(crate) fn peek_line(&mut self, max: usize) -> io::Result<&[u8]> {
+        let buffered = self.inner.fill_buf()?;
+        if buffered.is_empty() || buffered.contains(&b'\n') || buffered.len() >= max {
+            return Ok(self.inner.buffer());
+        }
+
+        let mut line = buffered.to_vec();
+        self.inner.consume(line.len());
+        let mut buf = [0; 256];
+        let result = loop {
+            match self.inner.get_mut().read(&mut buf) {
+                Ok(0) => break Ok(()),
+                Ok(n) => {
+                    line.extend_from_slice(&buf[..n]);
+                    if buf[..n].contains(&b'\n') || line.len() >= max {
+                        break Ok(());
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => break Err(e),
+            }
+        };
+
+        // A BufReader only reads more once its buffer is empty, so the line is
+        // put in front of the connection instead, to be read again from there.
+        let placeholder = BufReader::new(Box::new(ReadOnlyStream::new(vec![])) as _);
+        let inner = std::mem::replace(&mut self.inner, placeholder).into_inner();
+        self.inner = BufReader::new(Box::new(Prefixed {
+            prefix: Cursor::new(line),
+            inner,
+        }));
+        result?;
+        self.inner.fill_buf()
+    }
+
     pub(crate) fn set_unpoolable(&mut self) {
         self.pool_returner = PoolReturner::none();
     }
@@ -251,6 +459,7 @@ impl Stream {
     pub(crate) fn return_to_pool(mut self) -> io::Result<()> {
         // ensure stream can be reused
         self.reset()?;
+        self.set_tap(None);
         self.pool_returner.clone().return_to_pool(self);
         Ok(())
     }
@@ -334,12 +543,48 @@ pub(crate) fn connect_https(unit: &Unit, hostname: &str) -> Result<Stream, Error
     let (sock, remote_addr) = connect_host(unit, hostname, port)?;
 
     let tls_conf = &unit.agent.config.tls_config;
-    let https_stream = tls_conf.connect(hostname, Box::new(sock))?;
+    let https_stream = match unit.agent.config.timeout_tls_handshake {
+        Some(timeout) => tls_handshake_with_timeout(unit, hostname, sock, timeout)?,
+        None => tls_conf.connect(hostname, Box::new(sock))?,
+    };
     let pool_key = PoolKey::from_parts("https", hostname, port);
     let pool_returner = PoolReturner::new(&unit.agent, pool_key);
     Ok(Stream::new(https_stream, remote_addr, pool_returner))
 }
 
+/// The TLS handshake, with the socket timeouts lowered to `timeout` while it lasts.
+fn tls_handshake_with_timeout(
+    unit: &Unit,
+    hostname: &str,
+    sock: TcpStream,
+    timeout: Duration,
+) -> Result<Box<dyn ReadWrite>, Error> {
+    let timeout = match unit.deadline {
+        Some(deadline) => timeout.min(time_until_deadline(deadline)?),
+        None => timeout,
+    };
+    // The clone shares the socket, so the timeouts can be restored once
+    // the TLS stream owns it.
+    let handle = sock.try_clone()?;
+    let (read_timeout, write_timeout) = (handle.read_timeout()?, handle.write_timeout()?);
+    handle.set_read_timeout(Some(timeout))?;
+    handle.set_write_timeout(Some(timeout))?;
+
+    let started = Instant::now();
+    let tls_conf = &unit.agent.config.tls_config;
+    let stream = tls_conf.connect(hostname, Box::new(sock)).map_err(|e| {
+        if started.elapsed() >= timeout {
+            io_err_timeout(format!("tls handshake timed out after {:?}", timeout)).into()
+        } else {
+            e
+        }
+    })?;
+
+    handle.set_read_timeout(read_timeout)?;
+    handle.set_write_timeout(write_timeout)?;
+    Ok(stream)
+}
+
 /// If successful, returns a `TcpStream` and the remote address it is connected to.
 pub(crate) fn connect_host(
     unit: &Unit,
@@ -364,6 +609,7 @@ pub(crate) fn connect_host(
             .msg(format!("resolve dns name '{}'", netloc))
             .src(e)
     })?;
+    let sock_addrs = unit.agent.config.ip_family.filter(sock_addrs);
 
     if sock_addrs.is_empty() {
         return Err(ErrorKind::Dns.msg(format!("No ip address for {}", hostname)));
@@ -371,8 +617,87 @@ pub(crate) fn connect_host(
 
     let proto = proxy.as_ref().map(|proxy| proxy.proto);
 
+    let is_socks = proto.is_some() && Some(Proto::HTTP) != proto;
+    let attempt_delay = unit
+        .agent
+        .config
+        .connection_attempt_delay
+        .filter(|_| !is_socks && sock_addrs.len() > 1);
+
+    let mut retry = 0;
+    let (mut stream, remote_addr) = loop {
+        let err = match connect_any(
+            unit,
+            &netloc,
+            sock_addrs.clone(),
+            attempt_delay,
+            connect_deadline,
+            hostname,
+            port,
+        )? {
+            Ok(stream_and_addr) => break stream_and_addr,
+            Err(err) => err,
+        };
+        let delay = unit.agent.config.retry_backoff.delay(retry);
+        // A delay too long to add to the current time is past any deadline.
+        let out_of_time = connect_deadline
+            .map(|deadline| match Instant::now().checked_add(delay) {
+                Some(next) => next >= deadline,
+                None => true,
+            })
+            .unwrap_or(false);
+        if retry >= unit.agent.config.connect_retries || out_of_time {
+            return Err(ErrorKind::ConnectionFailed.msg("Connect error").src(err));
+        }
+        retry += 1;
+        debug!("retry {} connecting to {} in {:?}", retry, netloc, delay);
+        backoff_sleep(delay);
+    };
+
+    stream.set_nodelay(unit.no_delay)?;
+
+    if let Some(deadline) = unit.deadline {
+        stream.set_read_timeout(Some(time_until_deadline(deadline)?))?;
+    } else {
+        stream.set_read_timeout(unit.agent.config.timeout_read)?;
+    }
+
+    if let Some(deadline) = unit.deadline {
+        stream.set_write_timeout(Some(time_until_deadline(deadline)?))?;
+    } else {
+        stream.set_write_timeout(unit.agent.config.timeout_write)?;
+    }
+
+    if proto == Some(Proto::HTTP) && unit.url.scheme() == "https" {
+        if let Some(ref proxy) = proxy {
+            proxy_connect(unit, &mut stream, proxy, hostname, port)?;
+        }
+    }
+
+    Ok((stream, remote_addr))
+}
+
+/// One attempt at connecting to any of `sock_addrs`, with the error of the last
+/// address when all of them failed.
+#[allow(clippy::too_many_arguments)]
+fn connect_any(
+    unit: &Unit,
+    netloc: &str,
+    sock_addrs: Vec<SocketAddr>,
+    attempt_delay: Option<Duration>,
+    connect_deadline: Option<Instant>,
+    hostname: &str,
+    port: u16,
+) -> Result<io::Result<(TcpStream, SocketAddr)>, Error> {
+    let proxy = unit.agent.config.proxy.as_ref();
+    let proto = proxy.map(|proxy| proxy.proto);
+
+    if let Some(delay) = attempt_delay {
+        debug!("racing connections to {} at {:?}", netloc, sock_addrs);
+        return Ok(connect_racing(sock_addrs, delay, connect_deadline)?);
+    }
+
     let mut any_err = None;
-    let mut any_stream_and_addr = None;
     // Find the first sock_addr that accepts a connection
     for sock_addr in sock_addrs {
         // ensure connect timeout or overall timeout aren't yet hit.
@@ -388,75 +713,165 @@ pub(crate) fn connect_host(
         let stream = if proto.is_some() && Some(Proto::HTTP) != proto {
             connect_socks(
                 unit,
-                proxy.clone().unwrap(),
+                proxy.cloned().unwrap(),
                 connect_deadline,
                 sock_addr,
                 hostname,
                 port,
                 proto.unwrap(),
             )
-        } else if let Some(timeout) = timeout {
-            TcpStream::connect_timeout(&sock_addr, timeout)
         } else {
-            TcpStream::connect(sock_addr)
+            connect_addr(sock_addr, timeout)
         };
 
-        if let Ok(stream) = stream {
-            any_stream_and_addr = Some((stream, sock_addr));
-            break;
-        } else if let Err(err) = stream {
-            any_err = Some(err);
+        match stream {
+            Ok(stream) => return Ok(Ok((stream, sock_addr))),
+            Err(err) => any_err = Some(err),
         }
     }
 
-    let (mut stream, remote_addr) = if let Some(stream_and_addr) = any_stream_and_addr {
-        stream_and_addr
-    } else if let Some(e) = any_err {
-        return Err(ErrorKind::ConnectionFailed.msg("Connect error").src(e));
-    } else {
-        panic!("shouldn't happen: failed to connect to all IPs, but no error");
-    };
+    Ok(Err(any_err.expect(
+        "shouldn't happen: failed to connect to all IPs, but no error",
+    )))
+}
 
-    stream.set_nodelay(unit.agent.config.no_delay)?;
+/// Wait between connection attempts.
+fn backoff_sleep(delay: Duration) {
+    #[cfg(test)]
+    crate::test::record_sleep(delay);
+    #[cfg(not(test))]
+    std::thread::sleep(delay);
+}
 
-    if let Some(deadline) = unit.deadline {
-        stream.set_read_timeout(Some(time_until_deadline(deadline)?))?;
-    } else {
-        stream.set_read_timeout(unit.agent.config.timeout_read)?;
+fn connect_addr(addr: SocketAddr, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    #[cfg(test)]
+    crate::test::delay_connect(&addr);
+    match timeout {
+        Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+        None => TcpStream::connect(addr),
     }
+}
 
-    if let Some(deadline) = unit.deadline {
-        stream.set_write_timeout(Some(time_until_deadline(deadline)?))?;
-    } else {
-        stream.set_write_timeout(unit.agent.config.timeout_write)?;
-    }
+/// Connect to the first address that accepts, "Happy Eyeballs" style (RFC 8305).
+///
+/// The addresses are tried in turn, alternating between IPv6 and IPv4, but each
+/// attempt only gets `delay` to succeed before the next one starts alongside it.
+/// The outer error is for the deadline passing, the inner for every attempt failing.
+fn connect_racing(
+    sock_addrs: Vec<SocketAddr>,
+    delay: Duration,
+    deadline: Option<Instant>,
+) -> io::Result<io::Result<(TcpStream, SocketAddr)>> {
+    let mut addrs = interleave_families(sock_addrs).into_iter().peekable();
+    let (tx, rx) = mpsc::channel();
+    let mut pending = 0;
+    let mut last_err = None;
+    // When the next attempt starts, unless an attempt fails first. None if the
+    // delay is too long to ever pass.
+    let mut next_attempt = Some(Instant::now());
+
+    loop {
+        let next_due = matches!(next_attempt, Some(at) if Instant::now() >= at);
+        if pending == 0 || next_due {
+            if let Some(addr) = addrs.next() {
+                let timeout = match deadline {
+                    Some(deadline) => Some(time_until_deadline(deadline)?),
+                    None => None,
+                };
+                debug!("connecting to {}", addr);
+                let tx = tx.clone();
+                // A connection that loses the race is closed when the send fails.
+                thread::spawn(move || {
+                    let _ = tx.send((connect_addr(addr, timeout), addr));
+                });
+                pending += 1;
+                next_attempt = Instant::now().checked_add(delay);
+            }
+        }
+        if pending == 0 {
+            break;
+        }
 
-    if proto == Some(Proto::HTTP) && unit.url.scheme() == "https" {
-        if let Some(ref proxy) = proxy {
-            write!(
-                stream,
-                "{}",
-                proxy.connect(hostname, port, &unit.agent.config.user_agent)
-            )
-            .unwrap();
-            stream.flush()?;
+        let wait = match (addrs.peek(), next_attempt) {
+            (Some(_), Some(at)) => at.saturating_duration_since(Instant::now()),
+            _ => match deadline {
+                Some(deadline) => time_until_deadline(deadline)?,
+                // Every attempt will end, by the timeout of the OS if nothing else.
+                None => Duration::from_secs(u32::MAX as u64),
+            },
+        };
+        match rx.recv_timeout(wait) {
+            Ok((Ok(stream), addr)) => return Ok(Ok((stream, addr))),
+            Ok((Err(err), addr)) => {
+                debug!("connecting to {} failed: {}", addr, err);
+                pending -= 1;
+                last_err = Some(err);
+                // No point waiting out the delay after a failure.
+                next_attempt = Some(Instant::now());
+            }
+            Err(_) => {}
+        }
+    }
 
-            let mut proxy_response = Vec::new();
+    Ok(Err(last_err.expect("at least one connection attempt")))
+}
 
-            loop {
-                let mut buf = vec![0; 256];
-                let total = stream.read(&mut buf)?;
-                proxy_response.append(&mut buf);
-                if total < 256 {
-                    break;
-                }
-            }
+/// Reorder the addresses to alternate between the families, starting with the
+/// family of the first one.
+fn interleave_families(sock_addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let first_is_v6 = sock_addrs.first().map(|a| a.is_ipv6()).unwrap_or(false);
+    let (first, second): (Vec<_>, Vec<_>) = sock_addrs
+        .into_iter()
+        .partition(|a| a.is_ipv6() == first_is_v6);
+    let mut second = second.into_iter();
+    let mut out = vec![];
+    for addr in first {
+        out.push(addr);
+        out.extend(second.next());
+    }
+    out.extend(second);
+    out
+}
 
-            Proxy::verify_response(&proxy_response)?;
+/// Ask an HTTP proxy to open a tunnel to `hostname:port` on `stream`.
+fn proxy_connect(
+    unit: &Unit,
+    stream: &mut TcpStream,
+    proxy: &Proxy,
+    hostname: &str,
+    port: u16,
+) -> Result<(), Error> {
+    write!(
+        stream,
+        "{}",
+        proxy.connect(hostname, port, &unit.agent.config.user_agent)
+    )
+    .unwrap();
+    stream.flush()?;
+
+    // Read a byte at a time, to leave anything after the response head to the tunnel.
+    let mut proxy_response = Vec::new();
+    let mut byte = [0];
+    while !proxy_response.ends_with(b"\r\n\r\n") {
+        if stream.read(&mut byte)? == 0 {
+            break;
         }
+        proxy_response.push(byte[0]);
     }
 
-    Ok((stream, remote_addr))
+    Proxy::verify_response(&proxy_response)
+}
+
+/// Open a `CONNECT` tunnel to `hostname:port` through the agent's HTTP proxy.
+pub(crate) fn connect_tunnel(unit: &Unit, hostname: &str, port: u16) -> Result<TcpStream, Error> {
+    let proxy = match &unit.agent.config.proxy {
+        Some(proxy) if proxy.proto == Proto::HTTP => proxy.clone(),
+        _ => return Err(ErrorKind::ProxyConnect.msg("CONNECT tunnels need an HTTP proxy")),
+    };
+    // The unit has an http url, so connect_host only connects to the proxy.
+    let (mut stream, _) = connect_host(unit, hostname, port)?;
+    proxy_connect(unit, &mut stream, &proxy, hostname, port)?;
+    Ok(stream)
 }
 
 #[cfg(feature = "socks-proxy")]
@@ -471,6 +886,7 @@ fn socks_local_nslookup(
         .map_err(|e| {
             std::io::Error::new(io::ErrorKind::NotFound, format!("DNS failure: {}.", e))
         })?;
+    let addrs = unit.agent.config.ip_family.filter(addrs);
 
     if addrs.is_empty() {
         return Err(std::io::Error::new(
@@ -656,6 +1072,23 @@ mod tests {
         sync::{Arc, Mutex},
     };
 
+    #[test]
+    fn interleave_families() {
+        let v6a: SocketAddr = "[::1]:1".parse().unwrap();
+        let v6b: SocketAddr = "[::1]:2".parse().unwrap();
+        let v4a: SocketAddr = "127.0.0.1:1".parse().unwrap();
+        let v4b: SocketAddr = "127.0.0.1:2".parse().unwrap();
+        let v4c: SocketAddr = "127.0.0.1:3".parse().unwrap();
+        assert_eq!(
+            super::interleave_families(vec![v6a, v6b, v4a, v4b, v4c]),
+            vec![v6a, v4a, v6b, v4b, v4c]
+        );
+        assert_eq!(
+            super::interleave_families(vec![v4a, v4b, v6a]),
+            vec![v4a, v6a, v4b]
+        );
+    }
+
     // Returns all zeroes to `.read()` and logs how many times it's called
     struct ReadRecorder {
         reads: Arc<Mutex<Vec<usize>>>,
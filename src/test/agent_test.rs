@@ -2,8 +2,9 @@
 
 use crate::error::Error;
 use crate::testserver::{read_request, TestServer};
-use std::io::{self, Read, Write};
+use std::io::{self, BufRead, Read, Write};
 use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::thread;
 use std::time::Duration;
 
@@ -57,6 +58,302 @@ fn connection_reuse() {
     assert_eq!(resp.status(), 200);
 }
 
+#[test]
+fn send_bytes_no_body_returns_connection() {
+    let testserver = TestServer::new(idle_timeout_handler);
+    let url = format!("http://localhost:{}", testserver.port);
+    let agent = Agent::new();
+    let status = agent.post(&url).send_bytes_no_body(b"hello").unwrap();
+    assert_eq!(status, 200);
+    assert_eq!(agent.state.pool.len(), 1);
+}
+
+// Handler that only answers requests with a "Content-Length: 0" header.
+fn require_empty_body_handler(mut stream: TcpStream) -> io::Result<()> {
+    let headers = read_request(&stream);
+    if headers.headers().iter().any(|h| h == "Content-Length: 0") {
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+    } else {
+        stream.write_all(b"HTTP/1.1 411 Length Required\r\nContent-Length: 0\r\n\r\n")
+    }
+}
+
+#[test]
+fn send_empty_sets_content_length() {
+    let testserver = TestServer::new(require_empty_body_handler);
+    let url = format!("http://localhost:{}", testserver.port);
+    let agent = Agent::new();
+    let resp = agent.post(&url).send_empty().unwrap();
+    assert_eq!(resp.status(), 200);
+
+    let err = agent.post(&url).call().unwrap_err();
+    assert!(matches!(err, Error::Status(411, _)));
+}
+
+// Read the request line and headers, keeping the body in the reader.
+fn read_head(reader: &mut impl BufRead) -> io::Result<Vec<String>> {
+    let mut headers = vec![];
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            return Ok(headers);
+        }
+        headers.push(line.to_string());
+    }
+}
+
+// Read a body delimited by Content-Length.
+fn read_body(reader: &mut impl Read, headers: &[String]) -> io::Result<Vec<u8>> {
+    let len = headers
+        .iter()
+        .find_map(|h| h.strip_prefix("Content-Length: "))
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+    Ok(body)
+}
+
+// Read the request head and body, but reject requests with "Expect: 100-continue"
+// before the body is sent. The answer to the Expect header is sent in two pieces,
+// split before the status code.
+fn expect_handler(mut stream: TcpStream, reject: bool) -> io::Result<()> {
+    let mut reader = io::BufReader::new(stream.try_clone()?);
+    let headers = read_head(&mut reader)?;
+    if headers.iter().any(|h| h == "Expect: 100-continue") {
+        stream.set_nodelay(true)?;
+        stream.write_all(b"HTTP/1.1 ")?;
+        thread::sleep(Duration::from_millis(50));
+        if reject {
+            return stream.write_all(b"417 Expectation Failed\r\nContent-Length: 0\r\n\r\n");
+        }
+        stream.write_all(b"100 Continue\r\n\r\n")?;
+    }
+    let body = read_body(&mut reader, &headers)?;
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(&body)
+}
+
+#[test]
+fn expect_continue_417_retries_without_expect() {
+    let testserver = TestServer::new(|stream| expect_handler(stream, true));
+    let url = format!("http://localhost:{}", testserver.port);
+    let resp = Agent::new()
+        .post(&url)
+        .set("Expect", "100-continue")
+        .send_string("hello")
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.into_string().unwrap(), "hello");
+}
+
+#[test]
+fn expect_continue_sends_body_after_100() {
+    let testserver = TestServer::new(|stream| expect_handler(stream, false));
+    let url = format!("http://localhost:{}", testserver.port);
+    let resp = Agent::new()
+        .post(&url)
+        .set("Expect", "100-continue")
+        .send_string("hello")
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.into_string().unwrap(), "hello");
+}
+
+// Handler that answers the first request on the first connection, then reads
+// the second request and closes the connection without answering. Requests on
+// later connections get their body echoed back. Every server using it needs
+// its own `connections` counter.
+fn close_after_first_request(mut stream: TcpStream, connections: &AtomicUsize) -> io::Result<()> {
+    let mut reader = io::BufReader::new(stream.try_clone()?);
+    let headers = read_head(&mut reader)?;
+    if headers.is_empty() {
+        // The TestServer checking that it is up.
+        return Ok(());
+    }
+    if connections.fetch_add(1, Ordering::SeqCst) == 0 {
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")?;
+        let headers = read_head(&mut reader)?;
+        read_body(&mut reader, &headers)?;
+        return Ok(());
+    }
+    let body = read_body(&mut reader, &headers)?;
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(&body)
+}
+
+fn close_after_first_request_handler(stream: TcpStream) -> io::Result<()> {
+    static CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+    close_after_first_request(stream, &CONNECTIONS)
+}
+
+#[test]
+fn body_factory_retries_on_closed_connection() {
+    let testserver = TestServer::new(close_after_first_request_handler);
+    let url = format!("http://localhost:{}", testserver.port);
+    let agent = Agent::new();
+    agent.get(&url).call().unwrap().into_string().unwrap();
+    assert_eq!(agent.state.pool.len(), 1);
+
+    let calls = AtomicUsize::new(0);
+    let resp = agent
+        .put(&url)
+        .set("Content-Length", "5")
+        .send_with_body_factory(|| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Box::new(io::Cursor::new(b"hello".to_vec()))
+        })
+        .unwrap();
+    assert_eq!(resp.into_string().unwrap(), "hello");
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn seekable_body_retries_on_closed_connection() {
+    fn handler(stream: TcpStream) -> io::Result<()> {
+        static CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+        close_after_first_request(stream, &CONNECTIONS)
+    }
+
+    struct CountSeeks(io::Cursor<Vec<u8>>, usize);
+
+    impl Read for CountSeeks {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl io::Seek for CountSeeks {
+        fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+            self.1 += 1;
+            self.0.seek(pos)
+        }
+    }
+
+    let testserver = TestServer::new(handler);
+    let url = format!("http://localhost:{}", testserver.port);
+    let agent = Agent::new();
+    agent.get(&url).call().unwrap().into_string().unwrap();
+    assert_eq!(agent.state.pool.len(), 1);
+
+    let mut reader = CountSeeks(io::Cursor::new(b"hello world".to_vec()), 0);
+    // Start past the beginning, as a reader that was already used would be.
+    io::Seek::seek(&mut reader, io::SeekFrom::End(0)).unwrap();
+    let resp = agent.put(&url).send_seekable(&mut reader, 11).unwrap();
+    assert_eq!(resp.into_string().unwrap(), "hello world");
+    // Once when seeking to the end above, then once per attempt.
+    assert_eq!(reader.1, 3);
+}
+
+// Handler that sends the response body a little after the head, so that it isn't
+// buffered along with it.
+fn slow_body_handler(mut stream: TcpStream) -> io::Result<()> {
+    loop {
+        if read_request(&stream).path().is_empty() {
+            return Ok(());
+        }
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 8\r\n\r\n")?;
+        thread::sleep(Duration::from_millis(50));
+        stream.write_all(b"response")?;
+    }
+}
+
+#[test]
+fn max_total_connections() {
+    let testserver = TestServer::new(slow_body_handler);
+    let url = format!("http://localhost:{}", testserver.port);
+    let agent = builder()
+        .max_total_connections(1)
+        .timeout_connect(Duration::from_millis(200))
+        .build();
+
+    // The unread response keeps the only connection in use.
+    let resp = agent.get(&url).call().unwrap();
+    let err = agent.get(&url).call().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TooManyConnections);
+
+    // A request waits for the connection to be returned, then reuses it.
+    let reader = thread::spawn(move || resp.into_string().unwrap());
+    let resp = agent.get(&url).call().unwrap();
+    assert_eq!(reader.join().unwrap(), "response");
+    assert_eq!(resp.into_string().unwrap(), "response");
+    assert_eq!(agent.state.pool.len(), 1);
+}
+
+// Handler that allows only one request per connection with `Keep-Alive: max=1`.
+fn keep_alive_max_handler(mut stream: TcpStream) -> io::Result<()> {
+    read_request(&stream);
+    stream.write_all(
+        b"HTTP/1.1 200 OK\r\nKeep-Alive: timeout=5, max=1\r\nContent-Length: 8\r\n\r\nresponse",
+    )
+}
+
+#[test]
+fn keep_alive_max_closes_connection() {
+    let testserver = TestServer::new(keep_alive_max_handler);
+    let url = format!("http://localhost:{}", testserver.port);
+    let agent = Agent::new();
+    let resp = agent.get(&url).call().unwrap();
+    assert_eq!(resp.into_string().unwrap(), "response");
+    assert_eq!(agent.state.pool.len(), 0);
+}
+
+// Handler that reads a chunked request body and answers with its trailer section,
+// after checking the request declared the trailer and the body is "hello world".
+fn echo_trailers_handler(stream: TcpStream) -> io::Result<()> {
+    let mut reader = io::BufReader::new(stream.try_clone()?);
+    let headers = read_head(&mut reader)?;
+    if headers.is_empty() {
+        return Ok(());
+    }
+    assert!(headers.contains(&"Trailer: x-checksum-sha256".to_string()));
+    assert!(!headers.iter().any(|h| h.starts_with("Content-Length")));
+    let mut body = vec![];
+    loop {
+        let mut size = String::new();
+        reader.read_line(&mut size)?;
+        let size = usize::from_str_radix(size.trim(), 16).unwrap();
+        if size == 0 {
+            break;
+        }
+        let mut chunk = vec![0; size + 2];
+        reader.read_exact(&mut chunk)?;
+        body.extend_from_slice(&chunk[..size]);
+    }
+    assert_eq!(body, b"hello world");
+    let trailers = read_head(&mut reader)?.join("\n");
+    write!(
+        &stream,
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+        trailers.len(),
+        trailers
+    )
+}
+
+#[test]
+fn body_checksum_trailer() {
+    let testserver = TestServer::new(echo_trailers_handler);
+    let url = format!("http://localhost:{}", testserver.port);
+    let resp = put(&url)
+        .set("Content-Length", "11")
+        .send_with_body_checksum(&b"hello world"[..], Checksum::Sha256)
+        .unwrap();
+    assert_eq!(
+        resp.into_string().unwrap(),
+        "x-checksum-sha256: uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek="
+    );
+}
+
 #[test]
 fn connection_reuse_with_408() {
     let testserver = TestServer::new(idle_timeout_handler_408);
@@ -84,6 +381,59 @@ fn connection_reuse_with_408() {
     assert_eq!(resp.status(), 200);
 }
 
+// Handler that keeps answering requests on the same connection, counting
+// the connections it has seen.
+fn counting_keepalive_handler(mut stream: TcpStream, connections: &AtomicUsize) -> io::Result<()> {
+    connections.fetch_add(1, Ordering::SeqCst);
+    loop {
+        stream.set_nonblocking(false)?;
+        if read_request(&stream).path().is_empty() {
+            return Ok(());
+        }
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 8\r\n\r\nresponse")?;
+    }
+}
+
+fn verifier_connections(accept: bool) -> usize {
+    static ACCEPTING: AtomicUsize = AtomicUsize::new(0);
+    static REJECTING: AtomicUsize = AtomicUsize::new(0);
+    fn accepting(stream: TcpStream) -> io::Result<()> {
+        counting_keepalive_handler(stream, &ACCEPTING)
+    }
+    fn rejecting(stream: TcpStream) -> io::Result<()> {
+        counting_keepalive_handler(stream, &REJECTING)
+    }
+    let (testserver, connections) = if accept {
+        (TestServer::new(accepting), &ACCEPTING)
+    } else {
+        (TestServer::new(rejecting), &REJECTING)
+    };
+    let url = format!("http://localhost:{}", testserver.port);
+    let agent = builder()
+        .connection_verifier(move |conn: &dyn ReadWrite| {
+            assert!(conn.socket().is_some());
+            accept
+        })
+        .build();
+
+    agent.get(&url).call().unwrap().into_string().unwrap();
+    assert_eq!(agent.state.pool.len(), 1);
+    // TestServer::new connects once to check the server is up.
+    let before = connections.load(Ordering::SeqCst);
+    agent.get(&url).call().unwrap().into_string().unwrap();
+    connections.load(Ordering::SeqCst) - before
+}
+
+#[test]
+fn connection_verifier_accepts() {
+    assert_eq!(verifier_connections(true), 0);
+}
+
+#[test]
+fn connection_verifier_rejects() {
+    assert_eq!(verifier_connections(false), 1);
+}
+
 #[test]
 fn custom_resolver() {
     use std::io::Read;
@@ -111,6 +461,193 @@ fn custom_resolver() {
     assert_eq!(&server.join().unwrap(), b"GET / HTTP/1.1\r\n");
 }
 
+// Wait for another thread to set `flag`, for at most `timeout`. Returns whether it was set.
+fn wait_for(flag: &AtomicBool, timeout: Duration) -> bool {
+    let started = std::time::Instant::now();
+    while !flag.load(Ordering::SeqCst) {
+        if started.elapsed() > timeout {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    true
+}
+
+static LARGE_BODY_DISCONNECTED: AtomicBool = AtomicBool::new(false);
+
+// Handler that sends a large body until the client goes away.
+fn large_body_handler(mut stream: TcpStream) -> io::Result<()> {
+    if read_request(&stream).path().is_empty() {
+        return Ok(());
+    }
+    stream.set_nonblocking(false)?;
+    stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 1000000000\r\n\r\n")?;
+    let chunk = [b'x'; 16 * 1024];
+    loop {
+        if stream.write_all(&chunk).is_err() {
+            LARGE_BODY_DISCONNECTED.store(true, Ordering::SeqCst);
+            return Ok(());
+        }
+    }
+}
+
+#[test]
+fn drop_unread_body_closes_connection() {
+    let testserver = TestServer::new(large_body_handler);
+    let url = format!("http://localhost:{}", testserver.port);
+    let agent = agent();
+    let resp = agent.get(&url).call().unwrap();
+    let mut reader = resp.into_reader();
+    reader.read_exact(&mut [0; 100]).unwrap();
+
+    // The rest of the body isn't drained, the connection is closed.
+    drop(reader);
+    assert!(wait_for(&LARGE_BODY_DISCONNECTED, Duration::from_secs(1)));
+    assert_eq!(agent.state.pool.len(), 0);
+}
+
+// Handler that switches to a protocol echoing back what it reads, upper cased.
+fn upgrade_handler(mut stream: TcpStream) -> io::Result<()> {
+    if read_request(&stream).path().is_empty() {
+        return Ok(());
+    }
+    stream.write_all(b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: shout\r\n\r\nready:")?;
+    // read_request leaves the stream non-blocking.
+    stream.set_nonblocking(false)?;
+    let mut buf = [0; 5];
+    stream.read_exact(&mut buf)?;
+    stream.write_all(&buf.to_ascii_uppercase())
+}
+
+#[test]
+fn into_raw() {
+    let testserver = TestServer::new(upgrade_handler);
+    let url = format!("http://localhost:{}", testserver.port);
+    let resp = agent().get(&url).call().unwrap();
+    assert_eq!(resp.status(), 101);
+
+    let (mut received, mut conn) = resp.into_raw().unwrap();
+    conn.write_all(b"hello").unwrap();
+    conn.read_to_end(&mut received).unwrap();
+    assert_eq!(received, b"ready:HELLO");
+
+    // A body read in full leaves no connection to take.
+    let resp = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok"
+        .parse::<Response>()
+        .unwrap();
+    let resp = resp.into_raw().err().unwrap();
+    assert_eq!(resp.into_string().unwrap(), "ok");
+}
+
+#[test]
+fn ip_family() {
+    let testserver = TestServer::new(echo_host_handler);
+    let v4: std::net::SocketAddr = format!("127.0.0.1:{}", testserver.port).parse().unwrap();
+    // Nothing listens here, connecting to it would fail the request.
+    let v6: std::net::SocketAddr = "[::1]:9".parse().unwrap();
+
+    let agent = builder()
+        .resolver(move |_: &str| Ok(vec![v6, v4]))
+        .ip_family(crate::IpFamily::V4Only)
+        .build();
+    assert_eq!(agent.get("http://v4.test/").call().unwrap().status(), 200);
+
+    let agent = builder()
+        .resolver(move |_: &str| Ok(vec![v4]))
+        .ip_family(crate::IpFamily::V6Only)
+        .build();
+    let err = agent.get("http://v6.test/").call().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Dns);
+}
+
+#[test]
+fn connection_attempt_delay() {
+    let testserver = TestServer::new(echo_host_handler);
+    let v4: std::net::SocketAddr = format!("127.0.0.1:{}", testserver.port).parse().unwrap();
+    // An IPv6 address that is slow to connect, like on a broken IPv6 network.
+    let v6: std::net::SocketAddr = "[::1]:19".parse().unwrap();
+    test::set_connect_delay(v6, Duration::from_secs(3));
+
+    let agent = builder()
+        .resolver(move |_: &str| Ok(vec![v6, v4]))
+        .connection_attempt_delay(Duration::from_millis(50))
+        .build();
+    let started = std::time::Instant::now();
+    let resp = agent.get("http://dual.test/").call().unwrap();
+    assert_eq!(resp.remote_addr(), v4);
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn connect_retry_backoff() {
+    // Nothing listens on the port once the listener is dropped.
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let agent = builder()
+        .resolver(move |_: &str| Ok(vec![addr]))
+        .connect_retries(3)
+        .retry_backoff(crate::BackoffPolicy::Exponential {
+            initial: Duration::from_millis(10),
+            max: Duration::from_secs(1),
+        })
+        .build();
+    test::take_sleeps();
+    let err = agent.get("http://refused.test/").call().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionFailed);
+    let millis: Vec<_> = test::take_sleeps().iter().map(|d| d.as_millis()).collect();
+    assert_eq!(millis, vec![10, 20, 40]);
+}
+
+#[test]
+fn connect_retry_backoff_past_deadline() {
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let agent = builder()
+        .resolver(move |_: &str| Ok(vec![addr]))
+        .timeout_connect(Duration::from_secs(5))
+        .connect_retries(3)
+        .retry_backoff(crate::BackoffPolicy::Fixed(Duration::MAX))
+        .build();
+    test::take_sleeps();
+    let err = agent.get("http://refused.test/").call().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionFailed);
+    assert!(test::take_sleeps().is_empty());
+}
+
+// Handler that answers with the Host header of the request as body.
+fn echo_host_handler(mut stream: TcpStream) -> io::Result<()> {
+    let headers = read_request(&stream);
+    let host = headers
+        .headers()
+        .iter()
+        .find(|h| h.starts_with("Host: "))
+        .map(|h| h["Host: ".len()..].to_string())
+        .unwrap_or_default();
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+        host.len(),
+        host
+    )
+}
+
+#[test]
+fn host_header_port() {
+    let testserver = TestServer::new(echo_host_handler);
+    let addr: std::net::SocketAddr = format!("127.0.0.1:{}", testserver.port).parse().unwrap();
+    let agent = builder().resolver(move |_: &str| Ok(vec![addr])).build();
+    let host = |url: &str| agent.get(url).call().unwrap().into_string().unwrap();
+
+    assert_eq!(host("http://example.com/"), "example.com");
+    assert_eq!(host("http://example.com:80/"), "example.com");
+    assert_eq!(host("http://example.com:8080/"), "example.com:8080");
+    assert_eq!(host("http://example.com:443/"), "example.com:443");
+}
+
 #[cfg(feature = "cookies")]
 #[cfg(test)]
 fn cookie_and_redirect(mut stream: TcpStream) -> io::Result<()> {
@@ -207,3 +744,165 @@ fn dirty_streams_not_returned() -> Result<(), Error> {
     let _resp_to_succeed = agent.get(&url).call()?;
     Ok(())
 }
+
+static NONBLOCKING_RELEASE: AtomicBool = AtomicBool::new(false);
+
+// Handler that holds back the response until the test releases it.
+fn held_response_handler(mut stream: TcpStream) -> io::Result<()> {
+    if read_request(&stream).path().is_empty() {
+        return Ok(());
+    }
+    wait_for(&NONBLOCKING_RELEASE, Duration::from_secs(10));
+    stream.set_nonblocking(false)?;
+    stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")?;
+    Ok(())
+}
+
+#[test]
+fn call_nonblocking() {
+    let testserver = TestServer::new(held_response_handler);
+    let url = format!("http://localhost:{}/held", testserver.port);
+    let mut handle = agent().get(&url).call_nonblocking();
+    assert!(handle.try_get().is_none());
+
+    NONBLOCKING_RELEASE.store(true, Ordering::SeqCst);
+    let started = std::time::Instant::now();
+    let resp = loop {
+        if let Some(result) = handle.try_get() {
+            break result.unwrap();
+        }
+        assert!(started.elapsed() < Duration::from_secs(10));
+        thread::sleep(Duration::from_millis(10));
+    };
+    assert_eq!(resp.into_string().unwrap(), "ok");
+    assert!(handle.try_get().is_none());
+}
+
+// Proxy that accepts CONNECT to port 7 and echoes what it gets on the tunnel.
+fn echo_tunnel_handler(mut stream: TcpStream) -> io::Result<()> {
+    let headers = read_request(&stream);
+    if headers.path().is_empty() {
+        return Ok(());
+    }
+    stream.set_nonblocking(false)?;
+    if headers.path() != "echo.test:7" {
+        stream.write_all(b"HTTP/1.1 403 Forbidden\r\n\r\n")?;
+        return Ok(());
+    }
+    stream.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")?;
+    let mut buf = [0; 64];
+    loop {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        stream.write_all(&buf[..n])?;
+    }
+}
+
+#[test]
+fn connect_tunnel() {
+    let testserver = TestServer::new(echo_tunnel_handler);
+    let proxy = Proxy::new(format!("localhost:{}", testserver.port)).unwrap();
+    let agent = builder().proxy(proxy).build();
+
+    let mut tunnel = agent.connect_tunnel("echo.test", 7).unwrap();
+    tunnel.write_all(b"hello").unwrap();
+    let mut buf = [0; 5];
+    tunnel.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+
+    let err = agent.connect_tunnel("other.test", 7).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ProxyConnect);
+    assert!(err.to_string().contains("403"), "{}", err);
+}
+
+static CHUNKED_LINE_READ: AtomicBool = AtomicBool::new(false);
+static CHUNKED_WAIT_TIMED_OUT: AtomicBool = AtomicBool::new(false);
+
+// Handler that sends an event stream in chunks, holding back the second
+// chunk until the client has read the first line.
+fn event_stream_chunks_handler(mut stream: TcpStream) -> io::Result<()> {
+    if read_request(&stream).path().is_empty() {
+        return Ok(());
+    }
+    stream.set_nonblocking(false)?;
+    stream.write_all(
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nTransfer-Encoding: chunked\r\n\r\n",
+    )?;
+    stream.write_all(b"8\r\ndata: a\n\r\n")?;
+    stream.flush()?;
+    if !wait_for(&CHUNKED_LINE_READ, Duration::from_secs(5)) {
+        CHUNKED_WAIT_TIMED_OUT.store(true, Ordering::SeqCst);
+    }
+    stream.write_all(b"8\r\ndata: b\n\r\n0\r\n\r\n")?;
+    Ok(())
+}
+
+#[test]
+fn chunked_lines_arrive_promptly() {
+    let testserver = TestServer::new(event_stream_chunks_handler);
+    let url = format!("http://localhost:{}/events", testserver.port);
+    let resp = agent().get(&url).call().unwrap();
+    let mut lines = io::BufReader::new(resp.into_reader()).lines();
+
+    assert_eq!(lines.next().unwrap().unwrap(), "data: a");
+    CHUNKED_LINE_READ.store(true, Ordering::SeqCst);
+    assert_eq!(lines.next().unwrap().unwrap(), "data: b");
+    assert!(lines.next().is_none());
+    assert!(!CHUNKED_WAIT_TIMED_OUT.load(Ordering::SeqCst));
+}
+
+#[test]
+fn trace_hooks() {
+    use std::sync::{Arc, Mutex};
+
+    test::set_handler("/trace_hooks_found", |_unit| {
+        test::make_response(200, "OK", vec![], vec![])
+    });
+    test::set_handler("/trace_hooks_missing", |_unit| {
+        test::make_response(404, "Not Found", vec![], vec![])
+    });
+    let events = Arc::new(Mutex::new(vec![]));
+    let (start_events, end_events) = (events.clone(), events.clone());
+    let next_token = AtomicU64::new(1);
+    let agent = builder()
+        .trace_hooks(
+            move |req: &Request| {
+                let token = next_token.fetch_add(1, Ordering::SeqCst);
+                start_events
+                    .lock()
+                    .unwrap()
+                    .push(format!("start {} {}", token, req.url()));
+                token
+            },
+            move |token, result| {
+                let status = match result {
+                    Ok(resp) => resp.status(),
+                    Err(Error::Status(status, _)) => *status,
+                    Err(_) => 0,
+                };
+                end_events
+                    .lock()
+                    .unwrap()
+                    .push(format!("end {} {}", token, status));
+            },
+        )
+        .build();
+    agent.get("test://host/trace_hooks_found").call().unwrap();
+    agent
+        .get("test://host/trace_hooks_missing")
+        .call()
+        .unwrap_err();
+
+    let events = events.lock().unwrap();
+    assert_eq!(
+        *events,
+        vec![
+            "start 1 test://host/trace_hooks_found",
+            "end 1 200",
+            "start 2 test://host/trace_hooks_missing",
+            "end 2 404",
+        ]
+    );
+}
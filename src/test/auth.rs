@@ -0,0 +1,115 @@
+use crate::hash::{hex, md5};
+use crate::test;
+
+use super::super::*;
+
+const CHALLENGE: &str = "WWW-Authenticate: Digest realm=\"test@example.com\", \
+    qop=\"auth\", nonce=\"dcd98b7102dd2f0e8b11d0f600bfb0c093\", opaque=\"5ccc069c40\"";
+
+#[test]
+fn digest_auth_retries_with_response() {
+    test::set_handler("/digest_auth", |unit| {
+        assert!(!unit.has("Authorization"));
+        test::set_handler("/digest_auth", |unit| {
+            let authorization = unit.header("Authorization").unwrap();
+            let params = crate::auth::parse_challenges(authorization);
+            let p = |n| params[0].param(n).unwrap();
+            assert!(params[0].is_scheme("digest"));
+            assert_eq!(p("username"), "Mufasa");
+            assert_eq!(p("uri"), "/digest_auth?a=b");
+            assert_eq!(p("nc"), "00000001");
+            assert_eq!(p("opaque"), "5ccc069c40");
+
+            let ha1 = hex(&md5(b"Mufasa:test@example.com:Circle Of Life"));
+            let ha2 = hex(&md5(b"GET:/digest_auth?a=b"));
+            let expected = hex(&md5(format!(
+                "{}:dcd98b7102dd2f0e8b11d0f600bfb0c093:00000001:{}:auth:{}",
+                ha1,
+                p("cnonce"),
+                ha2
+            )
+            .as_bytes()));
+            assert_eq!(p("response"), expected);
+            test::make_response(200, "OK", vec![], b"welcome".to_vec())
+        });
+        test::make_response(401, "Unauthorized", vec![CHALLENGE], vec![])
+    });
+    let resp = get("test://host/digest_auth?a=b")
+        .auth_digest("Mufasa", "Circle Of Life")
+        .call()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.into_string().unwrap(), "welcome");
+}
+
+#[test]
+fn digest_auth_retries_only_once() {
+    test::set_handler("/digest_auth_once", |_| {
+        test::set_handler("/digest_auth_once", |unit| {
+            assert!(unit.has("Authorization"));
+            test::make_response(401, "Unauthorized", vec![CHALLENGE], vec![])
+        });
+        test::make_response(401, "Unauthorized", vec![CHALLENGE], vec![])
+    });
+    let err = get("test://host/digest_auth_once")
+        .auth_digest("Mufasa", "wrong")
+        .call()
+        .unwrap_err();
+    assert!(matches!(err, Error::Status(401, _)));
+}
+
+#[test]
+fn digest_auth_not_sent_across_hosts() {
+    test::set_handler("/digest_auth_redirect", |_| {
+        test::make_response(
+            302,
+            "Found",
+            vec!["Location: test://other.host/digest_auth_other"],
+            vec![],
+        )
+    });
+    test::set_handler("/digest_auth_other", |unit| {
+        assert!(!unit.has("Authorization"));
+        test::set_handler("/digest_auth_other", |_| {
+            panic!("digest challenge of another host was answered")
+        });
+        test::make_response(401, "Unauthorized", vec![CHALLENGE], vec![])
+    });
+    let agent = builder()
+        .redirect_auth_headers(RedirectAuthHeaders::SameHost)
+        .build();
+    let err = agent
+        .get("test://host/digest_auth_redirect")
+        .auth_digest("Mufasa", "Circle Of Life")
+        .call()
+        .unwrap_err();
+    assert!(matches!(err, Error::Status(401, _)));
+}
+
+#[test]
+fn digest_auth_follows_same_host_redirect() {
+    test::set_handler("/digest_auth_same", |_| {
+        test::make_response(
+            302,
+            "Found",
+            vec!["Location: /digest_auth_same_target"],
+            vec![],
+        )
+    });
+    test::set_handler("/digest_auth_same_target", |_| {
+        test::set_handler("/digest_auth_same_target", |unit| {
+            assert!(unit.has("Authorization"));
+            test::make_response(200, "OK", vec![], vec![])
+        });
+        test::make_response(401, "Unauthorized", vec![CHALLENGE], vec![])
+    });
+    let agent = builder()
+        .redirect_auth_headers(RedirectAuthHeaders::SameHost)
+        .build();
+    let resp = agent
+        .get("test://host/digest_auth_same")
+        .auth_digest("Mufasa", "Circle Of Life")
+        .call()
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+}
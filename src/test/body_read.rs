@@ -107,6 +107,33 @@ fn gzip_text() {
     assert_eq!(text, "hello world ".repeat(14).trim_end());
 }
 
+#[cfg(feature = "gzip")]
+#[test]
+fn gzip_concatenated_members() {
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    // Like `gzip -c a b`, each part is its own gzip member.
+    let mut body = vec![];
+    for part in ["first part, ", "second part"] {
+        let mut enc = GzEncoder::new(vec![], Compression::default());
+        enc.write_all(part.as_bytes()).unwrap();
+        body.extend(enc.finish().unwrap());
+    }
+    test::set_handler("/gzip_concatenated_members", move |_unit| {
+        let len = format!("content-length: {}", body.len());
+        test::make_response(
+            200,
+            "OK",
+            vec![&len, "content-encoding: gzip"],
+            body.clone(),
+        )
+    });
+    let resp = get("test://host/gzip_concatenated_members").call().unwrap();
+    let text = resp.into_string().unwrap();
+    assert_eq!(text, "first part, second part");
+}
+
 #[cfg(feature = "brotli")]
 #[test]
 fn brotli_text() {
@@ -128,3 +155,76 @@ fn brotli_text() {
     let text = resp.into_string().unwrap();
     assert_eq!(text, "hello world ".repeat(14).trim_end());
 }
+
+#[cfg(feature = "charset")]
+#[test]
+fn force_charset() {
+    // "日本語" in Shift_JIS, mislabeled as UTF-8.
+    fn handler(_unit: &crate::unit::Unit) -> Result<crate::stream::Stream, Error> {
+        test::make_response(
+            200,
+            "OK",
+            vec!["content-type: text/plain; charset=utf-8"],
+            vec![0x93, 0xFA, 0x96, 0x7B, 0x8C, 0xEA],
+        )
+    }
+    test::set_handler("/force_charset", handler);
+    test::set_handler("/force_charset_default", handler);
+
+    let text = get("test://host/force_charset")
+        .force_charset("shift_jis")
+        .call()
+        .unwrap()
+        .into_string()
+        .unwrap();
+    assert_eq!(text, "日本語");
+
+    let text = get("test://host/force_charset_default")
+        .call()
+        .unwrap()
+        .into_string()
+        .unwrap();
+    assert_ne!(text, "日本語");
+    assert!(text.contains('\u{fffd}'));
+}
+
+#[test]
+fn save_to_file() {
+    let dir = std::env::temp_dir().join(format!("ureq-save-to-file-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("body.txt");
+
+    test::set_handler("/save_to_file", |_unit| {
+        test::make_response(200, "OK", vec!["Content-Length: 5"], b"hello".to_vec())
+    });
+    let resp = get("test://host/save_to_file").call().unwrap();
+    assert_eq!(resp.save_to_file(&path).unwrap(), 5);
+    assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+    std::fs::remove_file(&path).unwrap();
+
+    // A body of exactly the limit is fine, one over it is not.
+    for path in ["/save_to_file_at_limit", "/save_to_file_too_large"] {
+        test::set_handler(path, |_unit| {
+            test::make_response(200, "OK", vec!["Content-Length: 5"], b"hello".to_vec())
+        });
+    }
+    let resp = get("test://host/save_to_file_at_limit").call().unwrap();
+    assert_eq!(resp.save_to_file_limited(&path, 5).unwrap(), 5);
+    std::fs::remove_file(&path).unwrap();
+    let resp = get("test://host/save_to_file_too_large").call().unwrap();
+    let err = resp.save_to_file_limited(&path, 4).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Io);
+    assert!(!path.exists());
+
+    // The body ends before the announced length.
+    test::set_handler("/save_to_file_short", |_unit| {
+        test::make_response(200, "OK", vec!["Content-Length: 10"], b"hello".to_vec())
+    });
+    let resp = get("test://host/save_to_file_short").call().unwrap();
+    let err = resp.save_to_file(&path).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Io);
+    assert!(!path.exists());
+    // Nor is the temporary file left behind.
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+    std::fs::remove_dir(&dir).unwrap();
+}
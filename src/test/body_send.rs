@@ -78,6 +78,17 @@ fn content_type_on_json() {
     assert!(recorder.contains("\r\nContent-Type: application/json\r\n"));
 }
 
+#[test]
+#[cfg(feature = "json")]
+fn ndjson_lines() {
+    let recorder = Recorder::register("/ndjson_lines");
+    let items = (1..=3).map(|id| serde_json::json!({ "id": id }));
+    post("test://host/ndjson_lines").send_ndjson(items).unwrap();
+    assert!(recorder.contains("\r\nContent-Type: application/x-ndjson\r\n"));
+    assert!(recorder.contains("\r\nTransfer-Encoding: chunked\r\n"));
+    assert!(recorder.contains("\r\n{\"id\":1}\n{\"id\":2}\n{\"id\":3}\n\r\n0\r\n\r\n"));
+}
+
 #[test]
 #[cfg(feature = "json")]
 fn content_type_not_overriden_on_json() {
@@ -93,3 +104,112 @@ fn content_type_not_overriden_on_json() {
         .unwrap();
     assert!(recorder.contains("\r\ncontent-type: text/plain\r\n"));
 }
+
+#[test]
+#[cfg(feature = "gzip")]
+fn auto_gzip_request() {
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    let agent = builder().auto_gzip_request(1024).build();
+
+    let recorder = Recorder::register("/auto_gzip_small");
+    agent
+        .post("test://host/auto_gzip_small")
+        .send_string("0123456789")
+        .unwrap();
+    assert!(recorder.contains("\r\nContent-Length: 10\r\n\r\n0123456789"));
+    assert!(!recorder.contains("Content-Encoding"));
+
+    let body = "0123456789".repeat(1024);
+    let recorder = Recorder::register("/auto_gzip_large");
+    agent
+        .post("test://host/auto_gzip_large")
+        .send_string(&body)
+        .unwrap();
+    let sent = recorder.to_vec();
+    let split = sent.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    let head = std::str::from_utf8(&sent[..split]).unwrap();
+    assert!(head.contains("\r\nContent-Encoding: gzip\r\n"));
+    let content_length = format!("\r\nContent-Length: {}\r\n", sent.len() - split);
+    assert!(head.contains(&content_length));
+
+    let mut decoded = String::new();
+    GzDecoder::new(&sent[split..])
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, body);
+
+    let recorder = Recorder::register("/auto_gzip_seekable");
+    agent
+        .post("test://host/auto_gzip_seekable")
+        .send_seekable(std::io::Cursor::new(body.clone()), body.len() as u64)
+        .unwrap();
+    assert!(recorder.contains("\r\nContent-Length: 10240\r\n\r\n0123456789"));
+    assert!(!recorder.contains("Content-Encoding"));
+}
+
+#[test]
+fn send_form_repeated_keys() {
+    let recorder = Recorder::register("/send_form_repeated_keys");
+    post("test://host/send_form_repeated_keys")
+        .send_form(&[("x", "1"), ("y", "a b"), ("x", "2")])
+        .unwrap();
+    assert!(recorder.contains("\r\nContent-Type: application/x-www-form-urlencoded\r\n"));
+    assert!(recorder.contains("\r\n\r\nx=1&y=a+b&x=2"));
+}
+
+#[test]
+fn chunked_bodies_on_one_agent() {
+    let agent = agent();
+    for (i, body) in ["a longer body to fill the buffer", "short", ""]
+        .iter()
+        .enumerate()
+    {
+        let path = format!("/chunked_bodies_on_one_agent{}", i);
+        let recorder = Recorder::register(&path);
+        agent
+            .post(&format!("test://host{}", path))
+            .send(body.as_bytes())
+            .unwrap();
+        let expected = if body.is_empty() {
+            "\r\n\r\n0\r\n\r\n".to_string()
+        } else {
+            format!("\r\n\r\n{:x}\r\n{}\r\n0\r\n\r\n", body.len(), body)
+        };
+        assert!(recorder.to_vec().ends_with(expected.as_bytes()));
+    }
+}
+
+#[test]
+fn content_md5() {
+    let recorder = Recorder::register("/content_md5");
+    put("test://host/content_md5")
+        .with_content_md5()
+        .send_string("hello")
+        .unwrap();
+    assert!(recorder.contains("\r\nContent-MD5: XUFAKrxLKna5cZ2REBfFkg==\r\n"));
+    assert!(recorder.contains("\r\n\r\nhello"));
+
+    // A streamed body is buffered, and still sent chunked.
+    let recorder = Recorder::register("/content_md5_reader");
+    put("test://host/content_md5_reader")
+        .with_content_md5()
+        .send(&b"hello"[..])
+        .unwrap();
+    assert!(recorder.contains("\r\nContent-MD5: XUFAKrxLKna5cZ2REBfFkg==\r\n"));
+    assert!(recorder.contains("\r\n\r\n5\r\nhello\r\n0\r\n\r\n"));
+}
+
+#[test]
+fn send_query_as_body() {
+    let recorder = Recorder::register("/query_as_body");
+    post("test://host/query_as_body")
+        .query("a", "1")
+        .query("b", "x y&z")
+        .send_query_as_body()
+        .unwrap();
+    assert!(recorder.contains("POST /query_as_body HTTP/1.1\r\n"));
+    assert!(recorder.contains("\r\nContent-Type: application/x-www-form-urlencoded\r\n"));
+    assert!(recorder.contains("\r\n\r\na=1&b=x+y%26z"));
+}
@@ -4,13 +4,16 @@ use crate::stream::{remote_addr_for_test, ReadOnlyStream, Stream};
 use crate::unit::Unit;
 use crate::ReadWrite;
 use once_cell::sync::Lazy;
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt;
 use std::io::{self, Cursor, Read, Write};
-use std::net::TcpStream;
+use std::net::{SocketAddr, TcpStream};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 mod agent_test;
+mod auth;
 mod body_read;
 mod body_send;
 mod query_string;
@@ -60,6 +63,39 @@ pub(crate) fn make_response(
     ))
 }
 
+pub(crate) static CONNECT_DELAYS: Lazy<Mutex<HashMap<SocketAddr, Duration>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Make TCP connections to `addr` take at least `delay`.
+pub(crate) fn set_connect_delay(addr: SocketAddr, delay: Duration) {
+    let mut delays = CONNECT_DELAYS.lock().unwrap_or_else(|e| e.into_inner());
+    delays.insert(addr, delay);
+}
+
+pub(crate) fn delay_connect(addr: &SocketAddr) {
+    let delay = {
+        let delays = CONNECT_DELAYS.lock().unwrap_or_else(|e| e.into_inner());
+        delays.get(addr).copied()
+    };
+    if let Some(delay) = delay {
+        std::thread::sleep(delay);
+    }
+}
+
+thread_local! {
+    static SLEEPS: RefCell<Vec<Duration>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Instead of sleeping, remember the delay for [`take_sleeps`].
+pub(crate) fn record_sleep(delay: Duration) {
+    SLEEPS.with(|sleeps| sleeps.borrow_mut().push(delay));
+}
+
+/// The delays recorded on this thread since the last call.
+pub(crate) fn take_sleeps() -> Vec<Duration> {
+    SLEEPS.with(|sleeps| sleeps.take())
+}
+
 pub(crate) fn resolve_handler(unit: &Unit) -> Result<Stream, Error> {
     let path = unit.url.path();
     // The only way this can panic is if
@@ -89,8 +125,7 @@ impl Recorder {
         recorder2
     }
 
-    #[cfg(feature = "charset")]
-    fn to_vec(self) -> Vec<u8> {
+    fn to_vec(&self) -> Vec<u8> {
         self.contents.lock().unwrap().clone()
     }
 
@@ -23,6 +23,24 @@ fn redirect_on() {
     assert_eq!(resp.header("x-foo").unwrap(), "bar");
 }
 
+#[test]
+fn redirect_error_url() {
+    test::set_handler("/redirect_error_url1", |_| {
+        test::make_response(
+            302,
+            "Go here",
+            vec!["Location: /redirect_error_url2"],
+            vec![],
+        )
+    });
+    test::set_handler("/redirect_error_url2", |_| {
+        Err(ErrorKind::ConnectionFailed.msg("connection refused"))
+    });
+    let err = get("test://host/redirect_error_url1").call().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionFailed);
+    assert_eq!(err.url(), Some("test://host/redirect_error_url2"));
+}
+
 #[test]
 fn redirect_many() {
     test::set_handler("/redirect_many1", |_| {
@@ -55,6 +73,25 @@ fn redirect_many() {
     assert!(matches!(result, Err(e) if e.kind() == ErrorKind::TooManyRedirects));
 }
 
+#[test]
+fn redirect_loop() {
+    test::set_handler("/redirect_loop_a", |_| {
+        test::make_response(302, "Go here", vec!["Location: /redirect_loop_b"], vec![])
+    });
+    test::set_handler("/redirect_loop_b", |_| {
+        test::make_response(302, "Go here", vec!["Location: /redirect_loop_a"], vec![])
+    });
+    let err = builder()
+        .redirects(10)
+        .detect_redirect_loops(true)
+        .build()
+        .get("test://host/redirect_loop_a")
+        .call()
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::RedirectLoop);
+    assert_eq!(err.url(), Some("test://host/redirect_loop_a"));
+}
+
 #[test]
 fn redirect_off() -> Result<(), Error> {
     test::set_handler("/redirect_off", |_| {
@@ -265,6 +302,15 @@ fn too_many_redirects() {
     assert_eq!(resp.get_url(), "test://host/malicious_redirect_10000");
 }
 
+#[test]
+fn redirect_unsupported_scheme() {
+    test::set_handler("/redirect_ftp", |_| {
+        test::make_response(302, "Go here", vec!["Location: ftp://host/"], vec![])
+    });
+    let err = get("test://host/redirect_ftp").call().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnsupportedRedirectScheme);
+}
+
 #[test]
 fn redirect_no_keep_authorization() {
     test::set_handler("/redir_no_keep_auth1", |unit| {
@@ -364,3 +410,37 @@ fn redirect_no_keep_auth_different_host() {
     assert!(resp.has("x-foo"));
     assert_eq!(resp.header("x-foo").unwrap(), "bar");
 }
+
+#[test]
+fn redirect_preserve_fragment() {
+    test::set_handler("/preserve_fragment1", |_| {
+        test::make_response(
+            302,
+            "Go here",
+            vec!["Location: /preserve_fragment2"],
+            vec![],
+        )
+    });
+    test::set_handler("/preserve_fragment3", |_| {
+        test::make_response(
+            302,
+            "Go here",
+            vec!["Location: /preserve_fragment4"],
+            vec![],
+        )
+    });
+    for path in ["/preserve_fragment2", "/preserve_fragment4"] {
+        test::set_handler(path, |_| test::make_response(200, "OK", vec![], vec![]));
+    }
+
+    let agent = builder().preserve_fragment(true).build();
+    let resp = agent
+        .get("test://host/preserve_fragment1#frag")
+        .call()
+        .unwrap();
+    assert_eq!(resp.get_url(), "test://host/preserve_fragment2#frag");
+
+    // Off by default.
+    let resp = get("test://host/preserve_fragment3#frag").call().unwrap();
+    assert_eq!(resp.get_url(), "test://host/preserve_fragment4");
+}
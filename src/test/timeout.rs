@@ -174,3 +174,54 @@ fn overall_timeout_reading_json() {
     }
     .expect("expected timeout but got something else");
 }
+
+// Accept the connection but never answer, like a server stuck in the TLS handshake.
+fn stalled_handshake_respond(_stream: TcpStream) -> io::Result<()> {
+    thread::sleep(Duration::from_secs(5));
+    Ok(())
+}
+
+#[test]
+#[cfg(any(feature = "tls", feature = "native-tls"))]
+fn tls_handshake_timeout() {
+    use std::time::Instant;
+
+    let server = TestServer::new(stalled_handshake_respond);
+    let url = format!("https://localhost:{}/", server.port);
+    let agent = builder()
+        .timeout_tls_handshake(Duration::from_millis(100))
+        .build();
+    let started = Instant::now();
+    let err = agent.get(&url).call().unwrap_err();
+
+    assert_eq!(err.kind(), ErrorKind::Io);
+    let ioe: Option<&io::Error> = err.source().and_then(|s| s.downcast_ref());
+    assert_eq!(ioe.map(|e| e.kind()), Some(io::ErrorKind::TimedOut));
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn deadline_in_the_past() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    let resolved = Arc::new(AtomicBool::new(false));
+    let resolved2 = resolved.clone();
+    let agent = builder()
+        .resolver(move |_: &str| {
+            resolved2.store(true, Ordering::SeqCst);
+            Ok(vec![])
+        })
+        .build();
+    let err = agent
+        .get("http://example.com/")
+        .deadline(Instant::now() - Duration::from_secs(1))
+        .call()
+        .unwrap_err();
+
+    assert_eq!(err.kind(), ErrorKind::Io);
+    let ioe: Option<&io::Error> = err.source().and_then(|s| s.downcast_ref());
+    assert_eq!(ioe.map(|e| e.kind()), Some(io::ErrorKind::TimedOut));
+    assert!(!resolved.load(Ordering::SeqCst));
+}
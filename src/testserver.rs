@@ -89,7 +89,6 @@ impl TestHeaders {
         }
     }
 
-    #[cfg(feature = "cookies")]
     pub fn headers(&self) -> &[String] {
         &self.0[1..]
     }
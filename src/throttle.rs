@@ -0,0 +1,104 @@
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Limits the throughput of a reader or writer with a token bucket.
+///
+/// Tokens (bytes) accumulate at `rate` per second, up to a tenth of a
+/// second's worth. That allows short bursts after a pause, while the
+/// average stays under the limit. The bucket starts empty.
+pub(crate) struct Throttle<T> {
+    inner: T,
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl<T> Throttle<T> {
+    pub fn new(inner: T, bytes_per_sec: u64) -> Self {
+        let rate = bytes_per_sec.max(1) as f64;
+        Throttle {
+            inner,
+            rate,
+            capacity: (rate / 10.0).max(1.0),
+            tokens: 0.0,
+            last: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.last = now;
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+    }
+
+    /// Wait until there is at least one token, and return how many
+    /// bytes of `want` can be transferred right now.
+    fn acquire(&mut self, want: usize) -> usize {
+        self.refill();
+        if self.tokens < 1.0 {
+            let wait = (1.0 - self.tokens) / self.rate;
+            thread::sleep(Duration::from_secs_f64(wait));
+            self.refill();
+        }
+        want.min(self.tokens.max(1.0) as usize)
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.tokens -= n as f64;
+    }
+}
+
+impl<R: Read> Read for Throttle<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let max = self.acquire(buf.len());
+        let n = self.inner.read(&mut buf[..max])?;
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<W: Write> Write for Throttle<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let max = self.acquire(buf.len());
+        let n = self.inner.write(&buf[..max])?;
+        self.consume(n);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_reads() {
+        let data = vec![0; 2_000];
+        let mut reader = Throttle::new(&data[..], 10_000);
+        let start = Instant::now();
+        let n = io::copy(&mut reader, &mut io::sink()).unwrap();
+        assert_eq!(n, 2_000);
+        assert!(start.elapsed() >= Duration::from_millis(190));
+    }
+
+    #[test]
+    fn limits_writes() {
+        let mut writer = Throttle::new(Vec::new(), 10_000);
+        let start = Instant::now();
+        writer.write_all(&[1; 2_000]).unwrap();
+        assert_eq!(writer.inner.len(), 2_000);
+        assert!(start.elapsed() >= Duration::from_millis(190));
+    }
+}
@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::fmt::{self, Display};
 use std::io::{self, Write};
 use std::ops::Range;
@@ -5,12 +6,16 @@ use std::time;
 
 use base64::{prelude::BASE64_STANDARD, Engine};
 use log::debug;
-use url::Url;
+use url::{Position, Url};
 
 #[cfg(feature = "cookies")]
 use cookie::Cookie;
 
+#[cfg(feature = "cookies")]
+use crate::cookies::is_public_suffix;
+
 use crate::agent::RedirectAuthHeaders;
+use crate::auth::DigestAuth;
 use crate::body::{self, BodySize, Payload, SizedReader};
 use crate::error::{Error, ErrorKind};
 use crate::header;
@@ -18,7 +23,8 @@ use crate::header::{get_header, Header};
 use crate::proxy::Proto;
 use crate::resolve::ArcResolver;
 use crate::response::Response;
-use crate::stream::{self, connect_test, Stream};
+use crate::stream::{self, connect_test, Stream, Tap};
+use crate::throttle::Throttle;
 use crate::Agent;
 
 /// A Unit is fully-built Request, ready to execute.
@@ -32,6 +38,18 @@ pub(crate) struct Unit {
     is_chunked: bool,
     headers: Vec<Header>,
     pub deadline: Option<time::Instant>,
+    pub digest_auth: Option<DigestAuth>,
+    /// Send an HTTP/0.9 request line and read the response without status or headers.
+    pub simple_request: bool,
+    /// Throttle the request and response bodies to this many bytes per second.
+    pub rate_limit: Option<u64>,
+    /// Whether to set no_delay on the socket for this request.
+    pub no_delay: bool,
+    /// Shown the bytes read and written on the connection.
+    pub tap: Option<Tap>,
+    /// Charset for the response to be decoded with, instead of the declared one.
+    #[cfg(feature = "charset")]
+    pub force_charset: Option<String>,
 }
 
 impl Unit {
@@ -86,13 +104,29 @@ impl Unit {
 
             let username = url.username();
             let password = url.password().unwrap_or("");
-            if (!username.is_empty() || !password.is_empty())
+            if agent.config.url_auth
+                && (!username.is_empty() || !password.is_empty())
                 && get_header(&headers, "authorization").is_none()
             {
-                let encoded = BASE64_STANDARD.encode(format!("{}:{}", username, password));
+                // The url crate keeps the userinfo percent-encoded.
+                let mut userinfo = percent_decode(username);
+                userinfo.push(b':');
+                userinfo.extend(percent_decode(password));
+                let encoded = BASE64_STANDARD.encode(userinfo);
                 extra.push(Header::new("Authorization", &format!("Basic {}", encoded)));
             }
 
+            // HTTP/1.0 defaults to closing the connection and HTTP/1.1 to keeping
+            // it alive. Ask for the other when that matches the pooling config.
+            if get_header(&headers, "connection").is_none() {
+                let pooling = !agent.state.pool.noop();
+                if agent.config.http10 && pooling {
+                    extra.push(Header::new("Connection", "keep-alive"));
+                } else if !agent.config.http10 && !pooling {
+                    extra.push(Header::new("Connection", "close"));
+                }
+            }
+
             #[cfg(feature = "cookies")]
             extra.extend(extract_cookies(agent, url).into_iter());
 
@@ -101,6 +135,13 @@ impl Unit {
 
         headers.append(&mut extra_headers);
 
+        let no_delay = match (agent.config.no_delay_threshold, &body.size) {
+            (None, _) => agent.config.no_delay,
+            (Some(_), BodySize::Empty) => true,
+            (Some(threshold), BodySize::Known(size)) => *size <= threshold,
+            (Some(_), BodySize::Unknown) => false,
+        };
+
         Unit {
             agent: agent.clone(),
             method: method.to_string(),
@@ -108,6 +149,13 @@ impl Unit {
             is_chunked,
             headers,
             deadline,
+            digest_auth: None,
+            simple_request: false,
+            rate_limit: None,
+            no_delay,
+            tap: None,
+            #[cfg(feature = "charset")]
+            force_charset: None,
         }
     }
 
@@ -128,6 +176,24 @@ impl Unit {
         header::get_all_headers(&self.headers, name)
     }
 
+    // Adds the Content-Length header left out by `new` for a sized body that is sent
+    // chunked, unless the user has set it themselves.
+    pub(crate) fn add_conflicting_length(&mut self, body: &SizedReader) {
+        if let BodySize::Known(size) = body.size {
+            if self.is_chunked && get_header(&self.headers, "content-length").is_none() {
+                let header = Header::new("Content-Length", &format!("{}", size));
+                self.headers.push(header);
+            }
+        }
+    }
+
+    // Returns true if the request waits for "100 Continue" before sending the body.
+    fn expects_continue(&self, body: &SizedReader) -> bool {
+        let expect = get_header(&self.headers, "expect");
+        let expect = expect.map(|v| v.eq_ignore_ascii_case("100-continue"));
+        expect.unwrap_or(false) && !matches!(body.size, BodySize::Empty)
+    }
+
     // Returns true if this request, with the provided body, is retryable.
     pub(crate) fn is_retryable(&self, body: &SizedReader) -> bool {
         // Per https://tools.ietf.org/html/rfc7231#section-8.1.3
@@ -136,6 +202,10 @@ impl Unit {
             "DELETE" | "GET" | "HEAD" | "OPTIONS" | "PUT" | "TRACE" => true,
             _ => false,
         };
+        // A body from a factory or a seekable reader can always be sent again.
+        if idempotent && (body.factory.is_some() || body.seekable.is_some()) {
+            return true;
+        }
         // Unsized bodies aren't retryable because we can't rewind the reader.
         // Sized bodies are retryable only if they are zero-length because of
         // coincidences of the current implementation - the function responsible
@@ -151,6 +221,9 @@ impl Unit {
     }
 }
 
+// AgentBuilder::max_url_length.
+pub(crate) const DEFAULT_MAX_URL_LENGTH: usize = 64 * 1_024;
+
 /// Perform a connection. Follows redirects.
 pub(crate) fn connect(
     mut unit: Unit,
@@ -158,18 +231,46 @@ pub(crate) fn connect(
     mut body: SizedReader,
 ) -> Result<Response, Error> {
     let mut history = vec![];
+    let mut digest_retried = false;
+    let mut fragment = unit.url.fragment().map(String::from);
+    // The requests made in this chain of redirects, for detect_redirect_loops.
+    let mut visited: Vec<(String, String)> = vec![];
     let mut resp = loop {
-        let resp = connect_inner(&unit, use_pooled, body, &history)?;
+        let max_url_length = unit.agent.config.max_url_length;
+        if unit.url[..Position::AfterQuery].len() > max_url_length {
+            return Err(ErrorKind::UrlTooLong
+                .msg(format!("url longer than {} bytes", max_url_length))
+                .with_url(unit.url));
+        }
+        let body_is_empty = matches!(body.size, BodySize::Empty | BodySize::Known(0));
+        let resp = connect_inner(&unit, use_pooled, body, &history)
+            .map_err(|e| e.with_url(unit.url.clone()))?;
+
+        // answer a digest challenge by retrying once with credentials.
+        if resp.status() == 401 && body_is_empty && !digest_retried {
+            let authorization = unit.digest_auth.as_ref().and_then(|auth| {
+                auth.authorization(&unit.method, &unit.url, &resp.auth_challenges())
+            });
+            if let Some(authorization) = authorization {
+                debug!("retrying {} {} with digest auth", unit.method, unit.url);
+                digest_retried = true;
+                header::add_header(&mut unit.headers, authorization);
+                body = Payload::Empty.into_read();
+                continue;
+            }
+        }
 
         // handle redirects
         if !(300..399).contains(&resp.status()) || unit.agent.config.redirects == 0 {
             break resp;
         }
         if history.len() + 1 >= unit.agent.config.redirects as usize {
-            return Err(ErrorKind::TooManyRedirects.msg(format!(
-                "reached max redirects ({})",
-                unit.agent.config.redirects
-            )));
+            return Err(ErrorKind::TooManyRedirects
+                .msg(format!(
+                    "reached max redirects ({})",
+                    unit.agent.config.redirects
+                ))
+                .with_url(unit.url));
         }
         // the location header
         let location = match resp.header("location") {
@@ -184,7 +285,18 @@ pub(crate) fn connect(
             ErrorKind::InvalidUrl
                 .msg(format!("Bad redirection: {}", location))
                 .src(e)
+                .with_url(url.clone())
         })?;
+        let scheme = new_url.scheme();
+        if !(scheme == "http" || scheme == "https" || (cfg!(test) && scheme == "test")) {
+            return Err(ErrorKind::UnsupportedRedirectScheme
+                .msg(format!("redirect to unsupported scheme {}", scheme))
+                .with_url(new_url));
+        }
+
+        if unit.agent.config.detect_redirect_loops {
+            visited.push((method.clone(), url[..Position::AfterQuery].to_string()));
+        }
 
         // perform the redirect differently depending on 3xx code.
         let new_method = match resp.status() {
@@ -209,7 +321,19 @@ pub(crate) fn connect(
             &new_url,
         );
 
+        if unit.agent.config.detect_redirect_loops {
+            let target = &new_url[..Position::AfterQuery];
+            if visited.iter().any(|(m, u)| *m == new_method && u == target) {
+                return Err(ErrorKind::RedirectLoop
+                    .msg(format!("redirect back to {} {}", new_method, target))
+                    .with_url(new_url));
+            }
+        }
+
         debug!("redirect {} {} -> {}", resp.status(), url, new_url);
+        if let Some(f) = new_url.fragment() {
+            fragment = Some(f.to_string());
+        }
         history.push(unit.url);
         body = Payload::Empty.into_read();
 
@@ -225,6 +349,11 @@ pub(crate) fn connect(
         });
 
         // recreate the unit to get a new hostname and cookies for the new host.
+        // digest credentials follow the same rules as the authorization header.
+        let digest_auth = unit.digest_auth.filter(|_| keep_auth_header);
+        let tap = unit.tap;
+        #[cfg(feature = "charset")]
+        let force_charset = unit.force_charset;
         unit = Unit::new(
             &unit.agent,
             &new_method,
@@ -233,7 +362,18 @@ pub(crate) fn connect(
             &body,
             unit.deadline,
         );
+        unit.digest_auth = digest_auth;
+        unit.tap = tap;
+        #[cfg(feature = "charset")]
+        {
+            unit.force_charset = force_charset;
+        }
+        digest_retried = false;
     };
+    // A redirect to a location without a fragment inherits the previous one.
+    if unit.agent.config.preserve_fragment && !history.is_empty() && resp.url.fragment().is_none() {
+        resp.url.set_fragment(fragment.as_deref());
+    }
     resp.history = history;
     Ok(resp)
 }
@@ -254,6 +394,14 @@ fn connect_inner(
     let method = &unit.method;
     // open socket
     let (mut stream, is_recycled) = connect_socket(unit, host, use_pooled)?;
+    stream.set_tap(unit.tap.clone());
+
+    // A pooled connection keeps the setting of the request it was opened for.
+    if is_recycled && unit.agent.config.no_delay_threshold.is_some() {
+        if let Some(socket) = stream.socket() {
+            socket.set_nodelay(unit.no_delay)?;
+        }
+    }
 
     if is_recycled {
         debug!("sending request (reused connection) {} {}", method, url);
@@ -276,15 +424,42 @@ fn connect_inner(
         }
     }
     let retryable = unit.is_retryable(&body);
+    let factory = body.factory;
+    let seekable = body.seekable;
 
-    // send the body (which can be empty now depending on redirects)
-    body::send_body(body, unit.is_chunked, &mut stream)?;
+    let answered_early = unit.expects_continue(&body) && await_continue(unit, &mut stream)?;
 
     // start reading the response to process cookies and redirects.
     // TODO: this unit.clone() bothers me. At this stage, we're not
     // going to use the unit (much) anymore, and it should be possible
     // to have ownership of it and pass it into the Response.
-    let result = Response::do_from_stream(stream, unit.clone());
+    let result = if answered_early {
+        // The server answered before getting the body, so we can't tell whether
+        // it will read the body as the next request. Don't reuse the connection.
+        stream.set_unpoolable();
+        let result = Response::do_from_stream(stream, unit.clone());
+        if matches!(&result, Ok(resp) if resp.status() == 417) {
+            debug!("retrying request without Expect header {} {}", method, url);
+            let mut unit = unit.clone();
+            unit.headers.retain(|h| !h.is_name("expect"));
+            // NOTE: this recurses at most once because the Expect header is removed.
+            return connect_inner(&unit, false, body, history);
+        }
+        result
+    } else {
+        // send the body (which can be empty now depending on redirects)
+        let buffers = &unit.agent.state.body_buffers;
+        match unit.rate_limit {
+            Some(rate) => body::send_body(
+                body,
+                unit.is_chunked,
+                &mut Throttle::new(&mut stream, rate),
+                buffers,
+            )?,
+            None => body::send_body(body, unit.is_chunked, &mut stream, buffers)?,
+        }
+        Response::do_from_stream(stream, unit.clone())
+    };
 
     // https://tools.ietf.org/html/rfc7230#section-6.3.1
     // When an inbound connection is closed prematurely, a client MAY
@@ -299,9 +474,13 @@ fn connect_inner(
     let resp = match result {
         Err(err) if err.connection_closed() && retryable && is_recycled => {
             debug!("retrying request {} {}: {}", method, url, err);
-            let empty = Payload::Empty.into_read();
+            let body = match (factory, seekable) {
+                (Some(factory), _) => Payload::Factory(factory).into_read(),
+                (None, Some((reader, len))) => Payload::Seekable(reader, len).into_read(),
+                (None, None) => Payload::Empty.into_read(),
+            };
             // NOTE: this recurses at most once because `use_pooled` is `false`.
-            return connect_inner(unit, false, empty, history);
+            return connect_inner(unit, false, body, history);
         }
         Err(e) => return Err(e),
         Ok(resp) => resp,
@@ -317,13 +496,56 @@ fn connect_inner(
     Ok(resp)
 }
 
+/// How long to wait for "100 Continue" before sending the body anyway.
+const EXPECT_CONTINUE_TIMEOUT: time::Duration = time::Duration::from_secs(1);
+
+/// Wait for the server to answer a request sent with "Expect: 100-continue".
+///
+/// Returns true if the server sent a final response instead of "100 Continue",
+/// meaning the body should not be sent. If the server doesn't answer in time,
+/// the body is sent anyway, as per
+/// <https://www.rfc-editor.org/rfc/rfc9110#section-10.1.1>. The "100 Continue"
+/// itself is left in the stream, and skipped when reading the response.
+fn await_continue(unit: &Unit, stream: &mut Stream) -> io::Result<bool> {
+    let mut wait = EXPECT_CONTINUE_TIMEOUT;
+    if let Some(deadline) = unit.deadline {
+        match deadline.checked_duration_since(time::Instant::now()) {
+            Some(left) if !left.is_zero() => wait = wait.min(left),
+            // Let reading the response report the timeout.
+            _ => return Ok(false),
+        }
+    }
+
+    stream.set_read_timeout(Some(wait))?;
+    // The status code of "HTTP/1.1 100 Continue" starts at index 9. The whole
+    // status line is read first, since it can arrive in pieces.
+    let answer = stream
+        .peek_line(unit.agent.config.max_header_line_length)
+        .map(|line| matches!(line.get(9), Some(b) if *b != b'1'));
+    stream.set_read_timeout(unit.agent.config.timeout_read)?;
+
+    match answer {
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ) =>
+        {
+            debug!("no 100 Continue within {:?}, sending body", wait);
+            Ok(false)
+        }
+        answer => answer,
+    }
+}
+
+/// The cookies from the agent's jar that a request to the url would send.
 #[cfg(feature = "cookies")]
-fn extract_cookies(agent: &Agent, url: &Url) -> Option<Header> {
-    let header_value = agent
+pub(crate) fn match_cookies(agent: &Agent, url: &Url) -> Vec<Cookie<'static>> {
+    agent
         .state
         .cookie_tin
         .get_request_cookies(url)
-        .iter()
+        .into_iter()
         // This guards against sending rfc non-compliant cookies, even if the user has
         // "prepped" their local cookie store with such cookies.
         .filter(|c| {
@@ -333,6 +555,13 @@ fn extract_cookies(agent: &Agent, url: &Url) -> Option<Header> {
             }
             is_ok
         })
+        .collect()
+}
+
+#[cfg(feature = "cookies")]
+fn extract_cookies(agent: &Agent, url: &Url) -> Option<Header> {
+    let header_value = match_cookies(agent, url)
+        .iter()
         .map(|c| c.to_string())
         .collect::<Vec<_>>()
         .join(";");
@@ -352,27 +581,90 @@ fn connect_socket(unit: &Unit, hostname: &str, use_pooled: bool) -> Result<(Stre
         return Err(ErrorKind::InsecureRequestHttpsOnly
             .msg("can't perform non https request with https_only set"));
     }
-    if use_pooled {
-        let pool = &unit.agent.state.pool;
-        let proxy = &unit.agent.config.proxy;
-        // The connection may have been closed by the server
-        // due to idle timeout while it was sitting in the pool.
-        // Loop until we find one that is still good or run out of connections.
-        while let Some(stream) = pool.try_get_connection(&unit.url, proxy.clone()) {
-            let server_closed = stream.server_closed()?;
-            if !server_closed {
+    let limit = unit.agent.state.connection_limit.as_ref();
+    // How long to wait for the connection limit, computed on first need.
+    let mut limit_deadline = None;
+    let permit = loop {
+        if use_pooled {
+            if let Some(stream) = pooled_stream(unit)? {
                 return Ok((stream, true));
             }
-            debug!("dropping stream from pool; closed by server: {:?}", stream);
         }
-    }
+        let limit = match limit {
+            Some(limit) => limit,
+            None => break None,
+        };
+        let seen = match limit.try_acquire() {
+            Ok(permit) => break Some(permit),
+            Err(seen) => seen,
+        };
+        // Closing an idle connection, likely to another host, makes room right away.
+        if unit.agent.state.pool.close_oldest() {
+            continue;
+        }
+        let deadline = *limit_deadline.get_or_insert_with(|| connection_limit_deadline(unit));
+        debug!(
+            "waiting for a connection below the limit of {}",
+            limit.max()
+        );
+        if !limit.wait(seen, deadline) {
+            return Err(ErrorKind::TooManyConnections
+                .msg(format!("reached max total connections ({})", limit.max())));
+        }
+    };
     let stream = match unit.url.scheme() {
         "http" => stream::connect_http(unit, hostname),
         "https" => stream::connect_https(unit, hostname),
         "test" => connect_test(unit),
         scheme => Err(ErrorKind::UnknownScheme.msg(format!("unknown scheme {}", scheme))),
     };
-    Ok((stream?, false))
+    let mut stream = stream?;
+    if let Some(permit) = permit {
+        stream.hold_permit(permit);
+    }
+    Ok((stream, false))
+}
+
+/// Take a connection for the unit from the pool, if there is a usable one.
+fn pooled_stream(unit: &Unit) -> Result<Option<Stream>, Error> {
+    let pool = &unit.agent.state.pool;
+    let proxy = &unit.agent.config.proxy;
+    // The connection may have been closed by the server
+    // due to idle timeout while it was sitting in the pool.
+    // Loop until we find one that is still good or run out of connections.
+    while let Some(stream) = pool.try_get_connection(&unit.url, proxy.clone()) {
+        let server_closed = stream.server_closed()?;
+        if server_closed {
+            debug!("dropping stream from pool; closed by server: {:?}", stream);
+            continue;
+        }
+        if let Some(verifier) = &unit.agent.state.connection_verifier {
+            if !verifier(stream.inner_ref()) {
+                debug!(
+                    "dropping stream from pool; rejected by verifier: {:?}",
+                    stream
+                );
+                continue;
+            }
+        }
+        return Ok(Some(stream));
+    }
+    Ok(None)
+}
+
+/// Waiting for the connection limit counts against the connect timeout and
+/// the overall deadline of the request. A connect timeout too long to add to
+/// the current time never passes.
+fn connection_limit_deadline(unit: &Unit) -> Option<time::Instant> {
+    let connect = unit
+        .agent
+        .config
+        .timeout_connect
+        .and_then(|t| time::Instant::now().checked_add(t));
+    match (connect, unit.deadline) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
 }
 
 fn can_propagate_authorization_on_redirect(
@@ -432,8 +724,23 @@ fn send_prelude(unit: &Unit, stream: &mut Stream) -> io::Result<()> {
         unit.url.path().into()
     };
 
+    let query = unit.url.query().unwrap_or_default();
+
+    // HTTP/0.9 requests are only the request line, without version or headers.
+    if unit.simple_request {
+        prelude.write_simple_request_line(&unit.method, &path, query)?;
+        debug!("writing prelude: {}", prelude);
+        stream.write_all(prelude.as_slice())?;
+        return Ok(());
+    }
+
     // request line
-    prelude.write_request_line(&unit.method, &path, unit.url.query().unwrap_or_default())?;
+    let version = if unit.agent.config.http10 {
+        "HTTP/1.0"
+    } else {
+        "HTTP/1.1"
+    };
+    prelude.write_request_line(&unit.method, &path, query, version)?;
 
     // host header if not set by user.
     if !header::has_header(&unit.headers, "host") {
@@ -462,14 +769,24 @@ fn send_prelude(unit: &Unit, stream: &mut Stream) -> io::Result<()> {
     if !header::has_header(&unit.headers, "accept") {
         prelude.write_header("Accept", "*/*")?;
     }
+    if let Some(languages) = &unit.agent.config.accept_language {
+        if !header::has_header(&unit.headers, "accept-language") {
+            prelude.write_header("Accept-Language", languages)?;
+        }
+    }
 
     // other headers
     for header in &unit.headers {
         if let Some(v) = header.value() {
+            let name = if unit.agent.config.normalize_header_case {
+                Cow::Owned(header::canonical_name(header.name()))
+            } else {
+                Cow::Borrowed(header.name())
+            };
             if is_header_sensitive(header) {
-                prelude.write_sensitive_header(header.name(), v)?;
+                prelude.write_sensitive_header(&name, v)?;
             } else {
-                prelude.write_header(header.name(), v)?;
+                prelude.write_header(&name, v)?;
             }
         }
     }
@@ -484,6 +801,30 @@ fn send_prelude(unit: &Unit, stream: &mut Stream) -> io::Result<()> {
     Ok(())
 }
 
+fn percent_decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        // from_str_radix also accepts a leading sign, so check the digits first.
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .filter(|h| h.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|h| std::str::from_utf8(h).ok());
+        match hex.map(|h| u8::from_str_radix(h, 16)) {
+            Some(Ok(b)) if bytes[i] == b'%' => {
+                out.push(b);
+                i += 3;
+            }
+            _ => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
 fn is_header_sensitive(header: &Header) -> bool {
     header.is_name("Authorization") || header.is_name("Cookie")
 }
@@ -502,12 +843,32 @@ impl PreludeBuilder {
         }
     }
 
-    fn write_request_line(&mut self, method: &str, path: &str, query: &str) -> io::Result<()> {
+    fn write_request_line(
+        &mut self,
+        method: &str,
+        path: &str,
+        query: &str,
+        version: &str,
+    ) -> io::Result<()> {
         write!(self.prelude, "{} {}", method, path,)?;
         if !query.is_empty() {
             write!(self.prelude, "?{}", query)?;
         }
-        write!(self.prelude, " HTTP/1.1\r\n")?;
+        write!(self.prelude, " {}\r\n", version)?;
+        Ok(())
+    }
+
+    fn write_simple_request_line(
+        &mut self,
+        method: &str,
+        path: &str,
+        query: &str,
+    ) -> io::Result<()> {
+        write!(self.prelude, "{} {}", method, path)?;
+        if !query.is_empty() {
+            write!(self.prelude, "?{}", query)?;
+        }
+        write!(self.prelude, "\r\n")?;
         Ok(())
     }
 
@@ -574,11 +935,16 @@ fn save_cookies(unit: &Unit, resp: &Response) {
             Err(_) => None,
             Ok(c) => {
                 // This guards against accepting rfc non-compliant cookies from a host.
-                if is_cookie_rfc_compliant(&c) {
-                    Some(c)
-                } else {
+                if !is_cookie_rfc_compliant(&c) {
                     debug!("ignore incoming non compliant cookie: {:?}", c);
                     None
+                } else if unit.agent.config.public_suffix_check
+                    && c.domain().map(is_public_suffix).unwrap_or(false)
+                {
+                    debug!("ignore incoming cookie for a public suffix: {:?}", c);
+                    None
+                } else {
+                    Some(c)
                 }
             }
         }
@@ -684,6 +1050,34 @@ mod tests {
         );
     }
 
+    #[test]
+    fn public_suffix_cookies_rejected() {
+        let agent = crate::builder().public_suffix_check(true).build();
+        let url: Url = "https://www.example.com/".parse().unwrap();
+        let unit = Unit::new(
+            &agent,
+            "GET",
+            &url,
+            vec![],
+            &Payload::Empty.into_read(),
+            None,
+        );
+        let resp = "HTTP/1.1 200 OK\r\n\
+            Set-Cookie: tld=1; Domain=com; Path=/\r\n\
+            Set-Cookie: site=2; Domain=example.com; Path=/\r\n\
+            \r\n"
+            .parse::<Response>()
+            .unwrap();
+        save_cookies(&unit, &resp);
+
+        let other: Url = "https://other.com/".parse().unwrap();
+        assert_eq!(extract_cookies(&agent, &other), None);
+        assert_eq!(
+            extract_cookies(&agent, &url),
+            Some(Header::new("Cookie", "site=2"))
+        );
+    }
+
     #[test]
     fn not_send_illegal_cookies() {
         // This prepares a cookie store with a cookie that isn't legal